@@ -0,0 +1,9 @@
+//! Records how the library was built, for `build_info()` of the Python module
+use std::env;
+
+fn main() {
+    for (name, var) in [("GIFSKI_BUILD_TARGET", "TARGET"), ("GIFSKI_BUILD_PROFILE", "PROFILE")] {
+        println!("cargo:rustc-env={}={}", name, env::var(var).unwrap_or_default());
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -237,6 +237,114 @@ GifskiError gifski_add_frame_rgb(gifski *handle,
  */
 void gifski_set_progress_callback(gifski *handle, int (*progress_callback)(void *user_data), void *user_data);
 
+/**
+ * Change the `repeat` setting given to `gifski_new`.
+ *
+ * If negative, looping is disabled. The number of times the sequence is repeated. 0 to loop forever.
+ *
+ * This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+ */
+GifskiError gifski_set_repeat(gifski *handle, int16_t repeat);
+
+/**
+ * 0-3, from the slowest encoding with the best quality to the fastest. 1 is the default,
+ * and 3 is the same as `fast` in `GifskiSettings`. This overrides `fast`.
+ *
+ * This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+ */
+GifskiError gifski_set_fast_level(gifski *handle, uint8_t level);
+
+/**
+ * 1-100. Lower values reduce motion, which makes the file smaller. Defaults to `quality` from `GifskiSettings`.
+ *
+ * This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+ */
+GifskiError gifski_set_motion_quality(gifski *handle, uint8_t quality);
+
+/**
+ * 1-100. Lower values allow more noise and artifacts in lossy compression. Defaults to `quality` from `GifskiSettings`.
+ *
+ * This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+ */
+GifskiError gifski_set_lossy_quality(gifski *handle, uint8_t quality);
+
+/**
+ * 0-1. Dithering reduces banding in gradients, but adds noise to flat colors. By default it depends on `quality`.
+ *
+ * This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+ */
+GifskiError gifski_set_dithering_level(gifski *handle, float level);
+
+/**
+ * Use only the given colors in the palettes of all frames, instead of choosing them for each frame.
+ *
+ * `colors` points to `count` RGBA colors, 4 bytes each. At most 256 colors can be given,
+ * or 255 if the frames need transparency, which takes one palette entry.
+ * The colors are copied.
+ *
+ * This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+ */
+GifskiError gifski_set_fixed_palette(gifski *handle, const unsigned char *colors, uint32_t count);
+
+/**
+ * Make pixels of exactly this color fully transparent.
+ *
+ * `color` points to 4 bytes of RGBA. The alpha of frames is made either fully opaque or fully
+ * transparent before comparing, so usually the alpha of the color should be 255.
+ *
+ * This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+ */
+GifskiError gifski_set_transparent_color(gifski *handle, const unsigned char *color);
+
+/**
+ * Add a comment extension with this text to the file. An empty string removes the comment.
+ *
+ * `comment` is a NUL-terminated string. It's written without any conversion, and the GIF spec
+ * expects 7-bit ASCII.
+ *
+ * This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+ */
+GifskiError gifski_set_comment(gifski *handle, const char *comment);
+
+/**
+ * 0-2, how frame timestamps are converted to delays, which are in centiseconds.
+ * 0 rounds to the nearest centisecond (the default), 1 rounds down, and 2 rounds to the nearest
+ * with halfway cases to the even centisecond. Timestamps are rounded rather than durations,
+ * so the rounding errors don't accumulate over frames.
+ *
+ * This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+ */
+GifskiError gifski_set_delay_rounding(gifski *handle, uint8_t rounding);
+
+/**
+ * Get a callback with error messages, instead of having them printed to stderr.
+ * This is intended mostly for logging and debugging, not for user interface.
+ *
+ * The callback gets a NUL-terminated UTF-8 message, which is valid only for the duration of the call,
+ * and an arbitrary pointer (`user_data`) as arguments. `user_data` can be `NULL`.
+ *
+ * The callback must be thread-safe (it will be called from another thread).
+ * It must remain valid at all times, until `gifski_finish` completes.
+ *
+ * This function must be called before `gifski_set_file_output()` to get messages from the writer.
+ */
+GifskiError gifski_set_error_message_callback(gifski *handle, void (*error_message_callback)(const char *message, void *user_data), void *user_data);
+
+/**
+ * Get a callback for problems that didn't stop the encoding, such as frames that have been skipped,
+ * because they were identical to the previous frame.
+ *
+ * The callback gets a short NUL-terminated identifier of the kind of problem (e.g. `frames_skipped`),
+ * a NUL-terminated UTF-8 message, both valid only for the duration of the call,
+ * and an arbitrary pointer (`user_data`) as arguments. `user_data` can be `NULL`.
+ *
+ * The callback must be thread-safe (it will be called from another thread).
+ * It must remain valid at all times, until `gifski_finish` completes.
+ *
+ * This function must be called before `gifski_set_file_output()` to take effect.
+ */
+GifskiError gifski_set_warning_callback(gifski *handle, void (*warning_callback)(const char *category, const char *message, void *user_data), void *user_data);
+
 /**
  * Start writing to the file at `destination_path` (overwrites if needed).
  * The file path must be ASCII or valid UTF-8.
@@ -248,6 +356,14 @@ void gifski_set_progress_callback(gifski *handle, int (*progress_callback)(void
  */
 GifskiError gifski_set_file_output(gifski *handle, const char *destination_path);
 
+/**
+ * Same as `gifski_set_file_output()`, but if the file exists, it's not overwritten,
+ * and `GIFSKI_ALREADY_EXISTS` is returned.
+ *
+ * The check is atomic, so the file can't be created by someone else in the meantime.
+ */
+GifskiError gifski_set_new_file_output(gifski *handle, const char *destination_path);
+
 /**
  * Start writing via callback (any buffer, file, whatever you want). This has to be called before any frames are added.
  * This call will not block.
@@ -43,7 +43,7 @@
 //! it will build `target/aarch64-apple-ios/release/libgifski.a` (ignore the warning about cdylib).
 
 use super::*;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::fs;
 use std::fs::File;
 use std::io;
@@ -56,7 +56,7 @@ use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
 mod c_api_error;
-use self::c_api_error::*;
+pub(crate) use self::c_api_error::GifskiError;
 
 /// Settings for creating a new encoder instance. See `gifski_new`
 #[repr(C)]
@@ -93,6 +93,8 @@ pub struct GifskiHandleInternal {
     writer: Mutex<Option<Writer>>,
     collector: Mutex<Option<Collector>>,
     progress: Mutex<Option<ProgressCallback>>,
+    error_callback: Mutex<Option<ErrorCallback>>,
+    warning_callback: Mutex<Option<WarningCallback>>,
     /// Bool set to true when the thread has been set up,
     /// prevents re-setting of the thread after finish()
     write_thread: Mutex<(bool, Option<thread::JoinHandle<GifskiError>>)>,
@@ -113,7 +115,7 @@ pub unsafe extern "C" fn gifski_new(settings: *const GifskiSettings) -> *const G
         height: if settings.height > 0 { Some(settings.height) } else { None },
         quality: settings.quality,
         fast: settings.fast,
-        repeat: if settings.repeat == -1 { Repeat::Finite(0) } else if settings.repeat == 0 { Repeat::Infinite } else { Repeat::Finite(settings.repeat as u16) },
+        repeat: repeat_from_c(settings.repeat),
     };
 
     if let Ok((collector, writer)) = new(s) {
@@ -122,12 +124,18 @@ pub unsafe extern "C" fn gifski_new(settings: *const GifskiSettings) -> *const G
             write_thread: Mutex::new((false, None)),
             collector: Mutex::new(Some(collector)),
             progress: Mutex::new(None),
+            error_callback: Mutex::new(None),
+            warning_callback: Mutex::new(None),
         })) as *const GifskiHandle
     } else {
         ptr::null_mut()
     }
 }
 
+fn repeat_from_c(repeat: i16) -> Repeat {
+    if repeat == -1 { Repeat::Finite(0) } else if repeat == 0 { Repeat::Infinite } else { Repeat::Finite(repeat as u16) }
+}
+
 /// Adds a frame to the animation. This function is asynchronous.
 ///
 /// File path must be valid UTF-8.
@@ -158,9 +166,9 @@ pub unsafe extern "C" fn gifski_add_frame_png_file(handle: *const GifskiHandle,
         return GifskiError::INVALID_INPUT;
     };
     if let Some(ref mut c) = *g.collector.lock().unwrap() {
-        c.add_frame_png_file(frame_number as usize, path, presentation_timestamp).into()
+        g.report(c.add_frame_png_file(frame_number as usize, path, presentation_timestamp))
     } else {
-        eprintln!("frames can't be added any more, because gifski_end_adding_frames has been called already");
+        g.print_error("frames can't be added any more, because gifski_end_adding_frames has been called already".into());
         GifskiError::INVALID_STATE
     }
 }
@@ -211,9 +219,9 @@ fn add_frame_rgba(handle: *const GifskiHandle, frame_number: u32, frame: Img<Cow
         None => return GifskiError::NULL_ARG,
     };
     if let Some(ref mut c) = *g.collector.lock().unwrap() {
-        c.add_frame_rgba_cow(frame_number as usize, frame, presentation_timestamp).into()
+        g.report(c.add_frame_rgba_cow(frame_number as usize, frame, presentation_timestamp))
     } else {
-        eprintln!("frames can't be added any more, because gifski_end_adding_frames has been called already");
+        g.print_error("frames can't be added any more, because gifski_end_adding_frames has been called already".into());
         GifskiError::INVALID_STATE
     }
 }
@@ -288,13 +296,347 @@ pub unsafe extern "C" fn gifski_set_progress_callback(handle: *const GifskiHandl
     };
     let t = g.write_thread.lock().unwrap();
     if t.0 {
-        eprintln!("tried to set progress callback after writing has already started");
+        g.print_error("tried to set progress callback after writing has already started".into());
         return GifskiError::INVALID_STATE;
     }
     *g.progress.lock().unwrap() = Some(ProgressCallback::new(cb, user_data));
     GifskiError::OK
 }
 
+/// Change the `repeat` setting given to `gifski_new`.
+///
+/// If negative, looping is disabled. The number of times the sequence is repeated. 0 to loop forever.
+///
+/// This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_repeat(handle: *const GifskiHandle, repeat: i16) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    if repeat < -1 {
+        return GifskiError::INVALID_INPUT;
+    }
+    let t = g.write_thread.lock().unwrap();
+    match &mut *g.writer.lock().unwrap() {
+        Some(writer) if !t.0 => {
+            writer.set_repeat(repeat_from_c(repeat));
+            GifskiError::OK
+        },
+        _ => {
+            g.print_error("tried to set repeat after writing has already started".into());
+            GifskiError::INVALID_STATE
+        },
+    }
+}
+
+/// 0-3, from the slowest encoding with the best quality to the fastest. 1 is the default,
+/// and 3 is the same as `fast` in `GifskiSettings`. This overrides `fast`.
+///
+/// This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_fast_level(handle: *const GifskiHandle, level: u8) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    if level > 3 {
+        return GifskiError::INVALID_INPUT;
+    }
+    if let Some(writer) = &mut *g.writer.lock().unwrap() {
+        #[allow(deprecated)]
+        writer.set_fast_level(level);
+        GifskiError::OK
+    } else {
+        g.print_error("tried to set fast level after writing has already started".into());
+        GifskiError::INVALID_STATE
+    }
+}
+
+/// 1-100. Lower values reduce motion, which makes the file smaller. Defaults to `quality` from `GifskiSettings`.
+///
+/// This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_motion_quality(handle: *const GifskiHandle, quality: u8) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    if quality == 0 || quality > 100 {
+        return GifskiError::INVALID_INPUT;
+    }
+    if let Some(writer) = &mut *g.writer.lock().unwrap() {
+        #[allow(deprecated)]
+        writer.set_motion_quality(quality);
+        GifskiError::OK
+    } else {
+        g.print_error("tried to set motion quality after writing has already started".into());
+        GifskiError::INVALID_STATE
+    }
+}
+
+/// 1-100. Lower values allow more noise and artifacts in lossy compression. Defaults to `quality` from `GifskiSettings`.
+///
+/// This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_lossy_quality(handle: *const GifskiHandle, quality: u8) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    if quality == 0 || quality > 100 {
+        return GifskiError::INVALID_INPUT;
+    }
+    if let Some(writer) = &mut *g.writer.lock().unwrap() {
+        #[allow(deprecated)]
+        writer.set_lossy_quality(quality);
+        GifskiError::OK
+    } else {
+        g.print_error("tried to set lossy quality after writing has already started".into());
+        GifskiError::INVALID_STATE
+    }
+}
+
+/// 0-1. Dithering reduces banding in gradients, but adds noise to flat colors. By default it depends on `quality`.
+///
+/// This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_dithering_level(handle: *const GifskiHandle, level: f32) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    if !(0. ..=1.).contains(&level) {
+        return GifskiError::INVALID_INPUT;
+    }
+    if let Some(writer) = &mut *g.writer.lock().unwrap() {
+        #[allow(deprecated)]
+        writer.set_dithering_level(level);
+        GifskiError::OK
+    } else {
+        g.print_error("tried to set dithering level after writing has already started".into());
+        GifskiError::INVALID_STATE
+    }
+}
+
+/// Use only the given colors in the palettes of all frames, instead of choosing them for each frame.
+///
+/// `colors` points to `count` RGBA colors, 4 bytes each. At most 256 colors can be given,
+/// or 255 if the frames need transparency, which takes one palette entry.
+/// The colors are copied.
+///
+/// This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_fixed_palette(handle: *const GifskiHandle, colors: *const RGBA8, count: u32) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    if count > 256 {
+        return GifskiError::INVALID_INPUT;
+    }
+    let colors = if count > 0 {
+        if colors.is_null() {
+            return GifskiError::NULL_ARG;
+        }
+        slice::from_raw_parts(colors, count as usize).to_vec()
+    } else {
+        Vec::new()
+    };
+    if let Some(writer) = &mut *g.writer.lock().unwrap() {
+        #[allow(deprecated)]
+        writer.set_fixed_colors(colors);
+        GifskiError::OK
+    } else {
+        g.print_error("tried to set fixed palette after writing has already started".into());
+        GifskiError::INVALID_STATE
+    }
+}
+
+/// Make pixels of exactly this color fully transparent.
+///
+/// `color` points to 4 bytes of RGBA. The alpha of frames is made either fully opaque or fully
+/// transparent before comparing, so usually the alpha of the color should be 255.
+///
+/// This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_transparent_color(handle: *const GifskiHandle, color: *const RGBA8) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    if color.is_null() {
+        return GifskiError::NULL_ARG;
+    }
+    if let Some(writer) = &mut *g.writer.lock().unwrap() {
+        #[allow(deprecated)]
+        writer.set_transparent_color(*color);
+        GifskiError::OK
+    } else {
+        g.print_error("tried to set transparent color after writing has already started".into());
+        GifskiError::INVALID_STATE
+    }
+}
+
+/// Add a comment extension with this text to the file. An empty string removes the comment.
+///
+/// `comment` is a NUL-terminated string. It's written without any conversion, and the GIF spec
+/// expects 7-bit ASCII.
+///
+/// This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_comment(handle: *const GifskiHandle, comment: *const c_char) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    if comment.is_null() {
+        return GifskiError::NULL_ARG;
+    }
+    let comment = CStr::from_ptr(comment).to_bytes().to_vec();
+    if let Some(writer) = &mut *g.writer.lock().unwrap() {
+        #[allow(deprecated)]
+        writer.set_comment(comment);
+        GifskiError::OK
+    } else {
+        g.print_error("tried to set comment after writing has already started".into());
+        GifskiError::INVALID_STATE
+    }
+}
+
+/// 0-2, how frame timestamps are converted to delays, which are in centiseconds.
+/// 0 rounds to the nearest centisecond (the default), 1 rounds down, and 2 rounds to the nearest
+/// with halfway cases to the even centisecond. Timestamps are rounded rather than durations,
+/// so the rounding errors don't accumulate over frames.
+///
+/// This function must be called before `gifski_set_file_output()`, otherwise it returns `GIFSKI_INVALID_STATE`.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_delay_rounding(handle: *const GifskiHandle, rounding: u8) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    let rounding = match rounding {
+        0 => DelayRounding::Round,
+        1 => DelayRounding::Floor,
+        2 => DelayRounding::NearestEven,
+        _ => return GifskiError::INVALID_INPUT,
+    };
+    if let Some(writer) = &mut *g.writer.lock().unwrap() {
+        #[allow(deprecated)]
+        writer.set_delay_rounding(rounding);
+        GifskiError::OK
+    } else {
+        g.print_error("tried to set delay rounding after writing has already started".into());
+        GifskiError::INVALID_STATE
+    }
+}
+
+/// Get a callback with error messages, instead of having them printed to stderr.
+/// This is intended mostly for logging and debugging, not for user interface.
+///
+/// The callback gets a NUL-terminated UTF-8 message, which is valid only for the duration of the call,
+/// and an arbitrary pointer (`user_data`) as arguments. `user_data` can be `NULL`.
+///
+/// The callback must be thread-safe (it will be called from another thread).
+/// It must remain valid at all times, until `gifski_finish` completes.
+///
+/// This function must be called before `gifski_set_file_output()` to get messages from the writer.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_error_message_callback(handle: *const GifskiHandle, cb: unsafe extern fn(*const c_char, *mut c_void), user_data: *mut c_void) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    *g.error_callback.lock().unwrap() = Some(ErrorCallback { callback: cb, arg: user_data });
+    GifskiError::OK
+}
+
+/// Get a callback for problems that didn't stop the encoding, such as frames that have been skipped,
+/// because they were identical to the previous frame.
+///
+/// The callback gets a short NUL-terminated identifier of the kind of problem (e.g. `frames_skipped`),
+/// a NUL-terminated UTF-8 message, both valid only for the duration of the call,
+/// and an arbitrary pointer (`user_data`) as arguments. `user_data` can be `NULL`.
+///
+/// The callback must be thread-safe (it will be called from another thread).
+/// It must remain valid at all times, until `gifski_finish` completes.
+///
+/// This function must be called before `gifski_set_file_output()` to take effect.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_warning_callback(handle: *const GifskiHandle, cb: unsafe extern fn(*const c_char, *const c_char, *mut c_void), user_data: *mut c_void) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    *g.warning_callback.lock().unwrap() = Some(WarningCallback { callback: cb, arg: user_data });
+    GifskiError::OK
+}
+
+struct WarningCallback {
+    callback: unsafe extern "C" fn(*const c_char, *const c_char, *mut c_void),
+    arg: *mut c_void,
+}
+
+unsafe impl Send for WarningCallback {}
+
+/// Progress and warnings for the writer
+struct CallbackReporter {
+    progress: Option<ProgressCallback>,
+    warning: Option<WarningCallback>,
+}
+
+impl ProgressReporter for CallbackReporter {
+    fn increase(&mut self) -> bool {
+        self.progress.as_mut().map_or(true, |p| p.increase())
+    }
+
+    fn done(&mut self, _msg: &str) {}
+
+    fn warning(&mut self, category: &str, msg: &str) {
+        if let Some(cb) = &self.warning {
+            let category = CString::new(category).unwrap_or_default();
+            let msg = CString::new(msg).unwrap_or_default();
+            unsafe { (cb.callback)(category.as_ptr(), msg.as_ptr(), cb.arg) }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct ErrorCallback {
+    callback: unsafe extern "C" fn(*const c_char, *mut c_void),
+    arg: *mut c_void,
+}
+
+unsafe impl Send for ErrorCallback {}
+
+impl ErrorCallback {
+    fn call(&self, msg: &str) {
+        let msg = CString::new(msg).unwrap_or_default();
+        unsafe { (self.callback)(msg.as_ptr(), self.arg) }
+    }
+}
+
+fn print_error(error_callback: Option<ErrorCallback>, msg: &str) {
+    match error_callback {
+        Some(cb) => cb.call(msg),
+        None => eprintln!("{}", msg),
+    }
+}
+
+impl GifskiHandleInternal {
+    fn print_error(&self, msg: String) {
+        print_error(*self.error_callback.lock().unwrap(), &msg);
+    }
+
+    fn report(&self, res: CatResult<()>) -> GifskiError {
+        if let Err(err) = &res {
+            self.print_error(err.to_string());
+        }
+        res.into()
+    }
+}
+
 /// Start writing to the `destination`. This has to be called before any frames are added.
 ///
 /// This call will not block.
@@ -306,15 +648,31 @@ pub unsafe extern "C" fn gifski_set_file_output(handle: *const GifskiHandle, des
         Some(g) => g,
         None => return GifskiError::NULL_ARG,
     };
-    let (file, path) = match prepare_for_file_writing(g, destination) {
+    let (file, path) = match prepare_for_file_writing(g, destination, true) {
         Ok(res) => res,
         Err(err) => return err,
     };
     gifski_write_thread_start(g, file, Some(path))
 }
 
+/// Same as `gifski_set_file_output`, but if the file exists, it's not overwritten,
+/// and `GIFSKI_ALREADY_EXISTS` is returned.
+///
+/// The check is atomic, so the file can't be created by someone else in the meantime.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_new_file_output(handle: *const GifskiHandle, destination: *const c_char) -> GifskiError {
+    let g = match borrow(handle) {
+        Some(g) => g,
+        None => return GifskiError::NULL_ARG,
+    };
+    let (file, path) = match prepare_for_file_writing(g, destination, false) {
+        Ok(res) => res,
+        Err(err) => return err,
+    };
+    gifski_write_thread_start(g, file, Some(path))
+}
 
-fn prepare_for_file_writing(g: &GifskiHandleInternal, destination: *const c_char) -> Result<(File, PathBuf), GifskiError> {
+fn prepare_for_file_writing(g: &GifskiHandleInternal, destination: *const c_char, overwrite: bool) -> Result<(File, PathBuf), GifskiError> {
     if destination.is_null() {
         return Err(GifskiError::NULL_ARG);
     }
@@ -325,10 +683,15 @@ fn prepare_for_file_writing(g: &GifskiHandleInternal, destination: *const c_char
     };
     let t = g.write_thread.lock().unwrap();
     if t.0 {
-        eprintln!("tried to start writing for the second time, after it has already started");
+        g.print_error("tried to start writing for the second time, after it has already started".into());
         return Err(GifskiError::INVALID_STATE);
     }
-    match File::create(path) {
+    let file = if overwrite {
+        File::create(path)
+    } else {
+        fs::OpenOptions::new().write(true).create_new(true).open(path)
+    };
+    match file {
         Ok(file) => Ok((file, path.into())),
         Err(err) => Err(err.kind().into()),
     }
@@ -387,18 +750,22 @@ pub unsafe extern "C" fn gifski_set_write_callback(handle: *const GifskiHandle,
 fn gifski_write_thread_start<W: 'static +  Write + Send>(g: &GifskiHandleInternal, file: W, path: Option<PathBuf>) -> GifskiError {
     let mut t = g.write_thread.lock().unwrap();
     if t.0 {
-        eprintln!("gifski_set_file_output/gifski_set_write_callback has been called already");
+        g.print_error("gifski_set_file_output/gifski_set_write_callback has been called already".into());
         return GifskiError::INVALID_STATE;
     }
     let writer = g.writer.lock().unwrap().take();
-    let mut user_progress = g.progress.lock().unwrap().take();
+    let mut reporter = CallbackReporter {
+        progress: g.progress.lock().unwrap().take(),
+        warning: g.warning_callback.lock().unwrap().take(),
+    };
+    let error_callback = *g.error_callback.lock().unwrap();
     let handle = thread::Builder::new().name("c-write".into()).spawn(move || {
         if let Some(writer) = writer {
-            let mut progress: &mut dyn ProgressReporter = &mut NoProgress {};
-            if let Some(cb) = &mut user_progress {
-                progress = &mut *cb;
+            let res = writer.write(file, &mut reporter);
+            if let Err(err) = &res {
+                print_error(error_callback, &err.to_string());
             }
-            match writer.write(file, progress).into() {
+            match res.into() {
                 res @ GifskiError::OK |
                 res @ GifskiError::ALREADY_EXISTS => res,
                 err => {
@@ -409,7 +776,7 @@ fn gifski_write_thread_start<W: 'static +  Write + Send>(g: &GifskiHandleInterna
                 },
             }
         } else {
-            eprintln!("gifski_set_file_output or gifski_write_* has been called once already");
+            print_error(error_callback, "gifski_set_file_output or gifski_write_* has been called once already");
             GifskiError::INVALID_STATE
         }
     });
@@ -451,7 +818,7 @@ pub unsafe extern "C" fn gifski_finish(g: *const GifskiHandle) -> GifskiError {
     if let Some(thread) = thread {
         thread.join().expect("writer thread failed")
     } else {
-        eprintln!("gifski_finish called before any output has been set");
+        g.print_error("gifski_finish called before any output has been set".into());
         GifskiError::OK // this will become INVALID_STATE once sync write support is dropped
     }
 }
@@ -600,3 +967,253 @@ fn c_incomplete() {
         assert_eq!(GifskiError::OK, gifski_finish(g));
     }
 }
+
+#[cfg(test)]
+fn test_handle() -> *const GifskiHandle {
+    let g = unsafe { gifski_new(&GifskiSettings {
+        width: 1, height: 1,
+        quality: 90,
+        fast: false,
+        repeat: -1,
+    })};
+    assert!(!g.is_null());
+    g
+}
+
+#[cfg(test)]
+unsafe extern "C" fn null_writer(_s: usize, _buf: *const u8, _user: *mut c_void) -> c_int {
+    GifskiError::OK as c_int
+}
+
+/// `user` must be a `Vec<u8>`
+#[cfg(test)]
+unsafe extern "C" fn vec_writer(size: usize, buf: *const u8, user: *mut c_void) -> c_int {
+    let out = &mut *(user as *mut Vec<u8>);
+    out.extend_from_slice(slice::from_raw_parts(buf, size));
+    GifskiError::OK as c_int
+}
+
+#[test]
+fn c_error_message_callback() {
+    let g = test_handle();
+    let mut messages = 0u32;
+    unsafe extern "C" fn cb(msg: *const c_char, user_data: *mut c_void) {
+        assert!(!CStr::from_ptr(msg).to_bytes().is_empty());
+        *(user_data as *mut u32) += 1;
+    }
+    unsafe {
+        assert_eq!(GifskiError::OK, gifski_set_error_message_callback(g, cb, (&mut messages) as *mut _ as _));
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+    assert_eq!(1, messages);
+}
+
+#[test]
+fn c_set_repeat() {
+    let g = test_handle();
+    unsafe {
+        assert_eq!(GifskiError::INVALID_INPUT, gifski_set_repeat(g, -2));
+        assert_eq!(GifskiError::OK, gifski_set_repeat(g, 3));
+        assert_eq!(GifskiError::OK, gifski_set_write_callback(g, Some(null_writer), ptr::null_mut()));
+        assert_eq!(GifskiError::INVALID_STATE, gifski_set_repeat(g, 0));
+        assert_eq!(GifskiError::OK, gifski_add_frame_rgb(g, 0, 1, 3, 1, &RGB::new(0,0,0), 5.0));
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+}
+
+#[test]
+fn c_set_qualities() {
+    let g = test_handle();
+    unsafe {
+        assert_eq!(GifskiError::INVALID_INPUT, gifski_set_fast_level(g, 4));
+        assert_eq!(GifskiError::OK, gifski_set_fast_level(g, 2));
+        assert_eq!(GifskiError::INVALID_INPUT, gifski_set_motion_quality(g, 0));
+        assert_eq!(GifskiError::INVALID_INPUT, gifski_set_lossy_quality(g, 101));
+        assert_eq!(GifskiError::OK, gifski_set_motion_quality(g, 50));
+        assert_eq!(GifskiError::OK, gifski_set_lossy_quality(g, 60));
+        assert_eq!(GifskiError::INVALID_INPUT, gifski_set_dithering_level(g, f32::NAN));
+        assert_eq!(GifskiError::OK, gifski_set_dithering_level(g, 0.));
+        assert_eq!(GifskiError::OK, gifski_set_write_callback(g, Some(null_writer), ptr::null_mut()));
+        assert_eq!(GifskiError::INVALID_STATE, gifski_set_lossy_quality(g, 70));
+        assert_eq!(GifskiError::OK, gifski_add_frame_rgb(g, 0, 1, 3, 1, &RGB::new(0,0,0), 5.0));
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+}
+
+#[test]
+fn c_fixed_palette() {
+    let g = test_handle();
+    let colors = vec![RGBA8::new(255, 0, 0, 255); 257];
+    unsafe {
+        assert_eq!(GifskiError::INVALID_INPUT, gifski_set_fixed_palette(g, colors.as_ptr(), 257));
+        assert_eq!(GifskiError::NULL_ARG, gifski_set_fixed_palette(g, ptr::null(), 2));
+        assert_eq!(GifskiError::OK, gifski_set_fixed_palette(g, colors.as_ptr(), 2));
+        assert_eq!(GifskiError::OK, gifski_set_write_callback(g, Some(null_writer), ptr::null_mut()));
+        assert_eq!(GifskiError::OK, gifski_add_frame_rgb(g, 0, 1, 3, 1, &RGB::new(0,0,0), 5.0));
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+}
+
+#[test]
+fn c_transparent_color() {
+    let g = test_handle();
+    unsafe {
+        assert_eq!(GifskiError::NULL_ARG, gifski_set_transparent_color(g, ptr::null()));
+        assert_eq!(GifskiError::OK, gifski_set_transparent_color(g, &RGBA8::new(255, 0, 255, 255)));
+        assert_eq!(GifskiError::OK, gifski_set_write_callback(g, Some(null_writer), ptr::null_mut()));
+        assert_eq!(GifskiError::INVALID_STATE, gifski_set_transparent_color(g, &RGBA8::new(0, 0, 0, 255)));
+        assert_eq!(GifskiError::OK, gifski_add_frame_rgb(g, 0, 1, 3, 1, &RGB::new(255, 0, 255), 5.0));
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+}
+
+#[test]
+fn c_add_frames_from_threads() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    let g = test_handle();
+    unsafe extern "C" fn cb(_s: usize, _buf: *const u8, user: *mut c_void) -> c_int {
+        let chunks = &*(user as *const AtomicU32);
+        chunks.fetch_add(1, Ordering::SeqCst);
+        GifskiError::OK as c_int
+    }
+    unsafe extern "C" fn pcb(user: *mut c_void) -> c_int {
+        let written = &*(user as *const AtomicU32);
+        written.fetch_add(1, Ordering::SeqCst);
+        1
+    }
+    let chunks = AtomicU32::new(0);
+    let written = AtomicU32::new(0);
+    unsafe {
+        gifski_set_progress_callback(g, pcb, (&written) as *const _ as _);
+        assert_eq!(GifskiError::OK, gifski_set_write_callback(g, Some(cb), (&chunks) as *const _ as _));
+    }
+    let handle = g as usize;
+    let threads: Vec<_> = (0..2).map(|t| thread::spawn(move || {
+        for i in (t..40).step_by(2) {
+            let color = RGB::new(i as u8 * 6, 0, 0);
+            let res = unsafe { gifski_add_frame_rgb(handle as *const GifskiHandle, i, 1, 3, 1, &color, i as f64 / 10.) };
+            assert_eq!(GifskiError::OK, res);
+        }
+    })).collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    unsafe {
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+    assert_eq!(40, written.load(Ordering::SeqCst));
+    assert!(chunks.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn c_comment() {
+    let g = test_handle();
+    let mut out = Vec::<u8>::new();
+    let comment = CString::new("made by gifski").unwrap();
+    unsafe {
+        assert_eq!(GifskiError::NULL_ARG, gifski_set_comment(g, ptr::null()));
+        assert_eq!(GifskiError::OK, gifski_set_comment(g, comment.as_ptr()));
+        assert_eq!(GifskiError::OK, gifski_set_write_callback(g, Some(vec_writer), (&mut out) as *mut _ as _));
+        assert_eq!(GifskiError::INVALID_STATE, gifski_set_comment(g, comment.as_ptr()));
+        assert_eq!(GifskiError::OK, gifski_add_frame_rgb(g, 0, 1, 3, 1, &RGB::new(0,0,0), 5.0));
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+    assert!(out.windows(17).any(|w| w == b"\x21\xFE\x0Emade by gifski"));
+}
+
+#[test]
+fn c_delay_rounding() {
+    let delays = |rounding: u8| {
+        let g = test_handle();
+        let mut out = Vec::<u8>::new();
+        unsafe {
+            assert_eq!(GifskiError::OK, gifski_set_delay_rounding(g, rounding));
+            assert_eq!(GifskiError::OK, gifski_set_write_callback(g, Some(vec_writer), (&mut out) as *mut _ as _));
+            assert_eq!(GifskiError::INVALID_STATE, gifski_set_delay_rounding(g, rounding));
+            // halfway between centiseconds
+            for (i, &t) in [0.0, 0.025, 0.05].iter().enumerate() {
+                assert_eq!(GifskiError::OK, gifski_add_frame_rgb(g, i as u32, 1, 3, 1, &RGB::new(i as u8 * 100, 0, 0), t));
+            }
+            assert_eq!(GifskiError::OK, gifski_finish(g));
+        }
+        // graphic control extensions
+        out.windows(6).filter(|w| w[..3] == [0x21, 0xF9, 0x04]).map(|w| u16::from_le_bytes([w[4], w[5]])).collect::<Vec<_>>()
+    };
+    assert_eq!(delays(0), [3, 2, 3]);
+    assert_eq!(delays(1), [2, 3, 2]);
+    assert_eq!(delays(2), [2, 3, 3]);
+
+    let g = test_handle();
+    unsafe {
+        assert_eq!(GifskiError::INVALID_INPUT, gifski_set_delay_rounding(g, 3));
+        assert_eq!(GifskiError::NULL_ARG, gifski_set_delay_rounding(ptr::null(), 0));
+        gifski_finish(g);
+    }
+}
+
+#[test]
+fn c_new_file_output() {
+    let g = test_handle();
+    let path = std::env::temp_dir().join(format!("gifski-c-new-file-output-{}.gif", std::process::id()));
+    fs::write(&path, b"existing").unwrap();
+    let c_path = CString::new(path.to_str().unwrap()).unwrap();
+    unsafe {
+        assert_eq!(GifskiError::ALREADY_EXISTS, gifski_set_new_file_output(g, c_path.as_ptr()));
+        assert_eq!(b"existing", &fs::read(&path).unwrap()[..]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(GifskiError::OK, gifski_set_new_file_output(g, c_path.as_ptr()));
+        assert_eq!(GifskiError::OK, gifski_add_frame_rgb(g, 0, 1, 3, 1, &RGB::new(0,0,0), 5.0));
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+    assert!(fs::read(&path).unwrap().starts_with(b"GIF89a"));
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn c_warning_callback() {
+    let g = test_handle();
+    unsafe extern "C" fn wcb(category: *const c_char, _msg: *const c_char, user: *mut c_void) {
+        let categories = &mut *(user as *mut Vec<String>);
+        categories.push(CStr::from_ptr(category).to_str().unwrap().to_string());
+    }
+    let mut categories = Vec::<String>::new();
+    unsafe {
+        assert_eq!(GifskiError::OK, gifski_set_warning_callback(g, wcb, (&mut categories) as *mut _ as _));
+        assert_eq!(GifskiError::OK, gifski_set_write_callback(g, Some(null_writer), ptr::null_mut()));
+        for i in 0..3 {
+            assert_eq!(GifskiError::OK, gifski_add_frame_rgb(g, i, 1, 3, 1, &RGB::new(0,0,0), i as f64));
+        }
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+    assert_eq!(categories, ["frames_skipped"]);
+}
+
+#[test]
+fn c_file_output_errors() {
+    let g = test_handle();
+    let dir = std::env::temp_dir().join(format!("gifski-c-file-output-errors-{}", std::process::id()));
+    let missing = CString::new(dir.join("missing/out.gif").to_str().unwrap()).unwrap();
+    unsafe {
+        assert_eq!(GifskiError::NOT_FOUND, gifski_set_file_output(g, missing.as_ptr()));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+        let read_only = CString::new(dir.join("out.gif").to_str().unwrap()).unwrap();
+        // root can write anyway
+        if File::create(dir.join("probe")).is_err() {
+            unsafe {
+                assert_eq!(GifskiError::PERMISSION_DENIED, gifski_set_file_output(g, read_only.as_ptr()));
+            }
+        }
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+    unsafe {
+        gifski_finish(g);
+    }
+}
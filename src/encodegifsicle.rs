@@ -11,16 +11,18 @@ pub(crate) struct Gifsicle<'w> {
     gif_writer: *mut Gif_Writer,
     out: &'w mut dyn Write,
     info: Gif_CompressInfo,
+    comment: Vec<u8>,
 }
 
 impl<'w> Gifsicle<'w> {
-    pub fn new(loss: u32, out: &'w mut (dyn std::io::Write + 'w)) -> Self {
+    pub fn new(loss: u32, out: &'w mut (dyn std::io::Write + 'w), comment: Vec<u8>) -> Self {
         unsafe {
             let mut g = Self {
                 gfs: ptr::null_mut(),
                 gif_writer: ptr::null_mut(),
                 info: std::mem::zeroed(),
                 out,
+                comment,
             };
             Gif_InitCompressInfo(&mut g.info);
             g.info.loss = loss as _;
@@ -57,7 +59,15 @@ impl Encoder for Gifsicle<'_> {
         if !self.gif_writer.is_null() {
             self.flush_writer()?;
             // fun fact: can't flush after the last write, because the writer gets freed,
-            // but the last write is literally just `;` (we don't use comments/extensions)
+            // but the last write is literally just `;`, so the comment goes before it
+            if !self.comment.is_empty() {
+                self.out.write_all(&[0x21, 0xFE])?;
+                for block in self.comment.chunks(255) {
+                    self.out.write_all(&[block.len() as u8])?;
+                    self.out.write_all(block)?;
+                }
+                self.out.write_all(&[0])?;
+            }
             self.out.write_all(std::slice::from_ref(&b';'))?;
             unsafe {
                 Gif_IncrementalWriteComplete(self.gif_writer, self.gfs);
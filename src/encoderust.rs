@@ -8,18 +8,32 @@ use std::io::Write;
 pub(crate) struct RustEncoder<W: Write> {
     writer: Option<W>,
     gif_enc: Option<gif::Encoder<W>>,
+    comment: Vec<u8>,
 }
 
 impl<W: Write> RustEncoder<W> {
-    pub fn new(writer: W) -> Self {
+    pub fn new(writer: W, comment: Vec<u8>) -> Self {
         Self {
             writer: Some(writer),
             gif_enc: None,
+            comment,
         }
     }
 }
 
 impl<W: Write> Encoder for RustEncoder<W> {
+    /// The trailer is written when `gif_enc` is dropped, so the comment goes before it, like in gifsicle
+    fn finish(&mut self) -> CatResult<()> {
+        if let Some(enc) = &mut self.gif_enc {
+            if !self.comment.is_empty() {
+                // sub-blocks are at most 255 bytes long
+                let blocks: Vec<_> = self.comment.chunks(255).collect();
+                enc.write_raw_extension(gif::AnyExtension(0xFE), &blocks)?;
+            }
+        }
+        Ok(())
+    }
+
     fn write_frame(&mut self, f: GIFFrame, delay: u16, settings: &Settings) -> CatResult<()> {
         let GIFFrame {left, top, pal, image, screen_width, screen_height, dispose, transparent_index} = f;
 
@@ -64,3 +78,41 @@ impl<W: Write> Encoder for RustEncoder<W> {
         Ok(())
     }
 }
+
+#[test]
+fn long_comment() {
+    use imgref::ImgVec;
+
+    let comment: Vec<u8> = (0..300).map(|i| b'a' + (i % 26) as u8).collect();
+    let mut out = Vec::new();
+    {
+        let mut enc = RustEncoder::new(&mut out, comment.clone());
+        let settings = Settings { width: None, height: None, quality: 90, fast: false, repeat: Repeat::Infinite };
+        enc.write_frame(GIFFrame {
+            left: 0, top: 0,
+            screen_width: 1, screen_height: 1,
+            image: ImgVec::new(vec![0], 1, 1),
+            pal: vec![RGBA8::new(1, 2, 3, 255)],
+            dispose: gif::DisposalMethod::Keep,
+            transparent_index: None,
+        }, 10, &settings).unwrap();
+        enc.finish().unwrap();
+    }
+
+    let start = out.windows(2).position(|w| w == [0x21, 0xFE]).expect("comment extension") + 2;
+    let mut read = Vec::new();
+    let mut pos = start;
+    while out[pos] != 0 {
+        let len = out[pos] as usize;
+        assert!(len <= 255);
+        read.extend_from_slice(&out[pos + 1..pos + 1 + len]);
+        pos += 1 + len;
+    }
+    assert_eq!(read, comment);
+    // the comment is right before the trailer
+    assert_eq!(&out[pos + 1..], b";");
+
+    let mut decoder = gif::DecodeOptions::new().read_info(&out[..]).unwrap();
+    assert!(decoder.read_next_frame().unwrap().is_some());
+    assert!(decoder.read_next_frame().unwrap().is_none());
+}
@@ -68,11 +68,54 @@ pub struct Settings {
     pub repeat: Repeat,
 }
 
+/// How frame timestamps are converted to GIF delays, which are in centiseconds
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DelayRounding {
+    /// To the nearest centisecond, the default
+    Round,
+    /// Down to a whole centisecond
+    Floor,
+    /// To the nearest centisecond, and halfway cases to the even one
+    NearestEven,
+}
+
+impl DelayRounding {
+    /// Times are rounded to whole centiseconds, and delays are differences of the rounded end times,
+    /// so the rounding errors don't add up over many frames.
+    fn centiseconds(self, time: f64) -> u64 {
+        // tolerance for times like 0.29 that aren't exact in binary
+        const EPSILON: f64 = 1e-6;
+        let cs = time * 100.0;
+        let rounded = match self {
+            Self::Round => cs.round(),
+            Self::Floor => (cs + EPSILON).floor(),
+            Self::NearestEven => {
+                let floor = cs.floor();
+                if (cs - floor - 0.5).abs() < EPSILON {
+                    if floor as u64 % 2 == 0 { floor } else { floor + 1.0 }
+                } else {
+                    cs.round()
+                }
+            },
+        };
+        rounded as u64
+    }
+}
+
 #[derive(Copy, Clone)]
 #[non_exhaustive]
 struct SettingsExt {
     pub s: Settings,
     pub extra_effort: bool,
+    /// libimagequant's speed 1-10, or `None` for its default
+    pub quantization_speed: Option<u8>,
+    pub motion_quality: u8,
+    pub lossy_quality: u8,
+    /// 0-1, or `None` to choose based on quality
+    pub dithering_level: Option<f32>,
+    /// Pixels of exactly this color are made transparent
+    pub transparent_color: Option<RGBA8>,
+    pub delay_rounding: DelayRounding,
 }
 
 impl Settings {
@@ -86,9 +129,11 @@ impl Settings {
     pub fn dimensions_for_image(&self, width: usize, height: usize) -> (usize, usize) {
         dimensions_for_image((width, height), (self.width, self.height))
     }
+}
 
+impl SettingsExt {
     pub(crate) fn gifsicle_loss(&self) -> u32 {
-        (100. / 6. - self.quality as f32 / 6.).powf(1.75).ceil() as u32
+        (100. / 6. - self.lossy_quality as f32 / 6.).powf(1.75).ceil() as u32
     }
 }
 
@@ -118,6 +163,10 @@ pub struct Writer {
     /// Input frame decoder results
     queue_iter: Option<OrdQueueIter<DecodedImage>>,
     settings: SettingsExt,
+    /// If not empty, the only colors used in the palette (besides transparency)
+    fixed_colors: Vec<RGBA8>,
+    /// If not empty, written in a comment extension
+    comment: Vec<u8>,
 }
 
 struct GIFFrame {
@@ -194,7 +243,15 @@ pub fn new(settings: Settings) -> CatResult<(Collector, Writer)> {
             settings: SettingsExt {
                 s: settings,
                 extra_effort: false,
-            }
+                quantization_speed: None,
+                motion_quality: settings.quality,
+                lossy_quality: settings.quality,
+                dithering_level: None,
+                transparent_color: None,
+                delay_rounding: DelayRounding::Round,
+            },
+            fixed_colors: Vec::new(),
+            comment: Vec::new(),
         },
     ))
 }
@@ -346,23 +403,90 @@ impl LastFrameDuration {
 
 /// Encode collected frames
 impl Writer {
+    /// Change the looping method set in `Settings`. Only works before writing has started.
+    pub fn set_repeat(&mut self, repeat: Repeat) {
+        self.settings.s.repeat = repeat;
+    }
+
     #[deprecated(note = "please don't use, it will be in Settings eventually")]
     #[doc(hidden)]
     pub fn set_extra_effort(&mut self) {
         self.settings.extra_effort = true;
     }
 
+    /// 0-3, from the slowest encoding with the best quality to the fastest.
+    /// 1 is the default, and 3 is the same as `Settings.fast`.
+    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[doc(hidden)]
+    pub fn set_fast_level(&mut self, level: u8) {
+        self.settings.s.fast = level >= 3;
+        self.settings.extra_effort = level == 0;
+        self.settings.quantization_speed = if level == 2 { Some(7) } else { None };
+    }
+
+    /// 1-100. Lower values reduce motion, which makes the file smaller.
+    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[doc(hidden)]
+    pub fn set_motion_quality(&mut self, q: u8) {
+        self.settings.motion_quality = q;
+    }
+
+    /// 1-100. Lower values allow more noise and artifacts in gifsicle's lossy compression.
+    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[doc(hidden)]
+    pub fn set_lossy_quality(&mut self, q: u8) {
+        self.settings.lossy_quality = q;
+    }
+
+    /// 0-1. Dithering reduces banding in gradients, but adds noise to flat colors.
+    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[doc(hidden)]
+    pub fn set_dithering_level(&mut self, level: f32) {
+        self.settings.dithering_level = Some(level);
+    }
+
+    /// Make pixels of exactly this color transparent. Frames' alpha has already been made binary,
+    /// so usually only opaque colors match.
+    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[doc(hidden)]
+    pub fn set_transparent_color(&mut self, color: RGBA8) {
+        self.settings.transparent_color = Some(color);
+    }
+
+    /// Use only these colors in all frames. At most 256, or 255 if frames need transparency.
+    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[doc(hidden)]
+    pub fn set_fixed_colors(&mut self, colors: Vec<RGBA8>) {
+        self.fixed_colors = colors;
+    }
+
+    /// Text of a comment extension to add to the file. Not written if empty.
+    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[doc(hidden)]
+    pub fn set_comment(&mut self, comment: Vec<u8>) {
+        self.comment = comment;
+    }
+
+    /// How timestamps are converted to delays in centiseconds
+    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[doc(hidden)]
+    pub fn set_delay_rounding(&mut self, rounding: DelayRounding) {
+        self.settings.delay_rounding = rounding;
+    }
+
     /// `importance_map` is computed from previous and next frame.
     /// Improves quality of pixels visible for longer.
     /// Avoids wasting palette on pixels identical to the background.
     ///
     /// `background` is the previous frame.
-    fn quantize(image: ImgVec<RGBA8>, importance_map: &[u8], first_frame: bool, needs_transparency: bool, prev_frame_keeps: bool, SettingsExt {s: settings, extra_effort}: &SettingsExt) -> CatResult<(Attributes, QuantizationResult, Image<'static>)> {
+    fn quantize(image: ImgVec<RGBA8>, importance_map: &[u8], first_frame: bool, needs_transparency: bool, prev_frame_keeps: bool, SettingsExt {s: settings, extra_effort, quantization_speed, ..}: &SettingsExt, fixed_colors: &[RGBA8]) -> CatResult<(Attributes, QuantizationResult, Image<'static>)> {
         let mut liq = Attributes::new();
         if settings.fast {
             liq.set_speed(10)?;
         } else if *extra_effort {
             liq.set_speed(1)?;
+        } else if let Some(speed) = *quantization_speed {
+            liq.set_speed(speed.into())?;
         }
         let quality = if !first_frame {
             settings.color_quality()
@@ -370,6 +494,9 @@ impl Writer {
             100 // the first frame is too important to ruin it
         };
         liq.set_quality(0, quality)?;
+        if !fixed_colors.is_empty() {
+            liq.set_max_colors((fixed_colors.len() as u32 + needs_transparency as u32).max(2))?;
+        }
         let (buf, width, height) = image.into_contiguous_buf();
         let mut img = liq.new_image(buf, width, height, 0.)?;
         // only later remapping tracks which area has been damanged by transparency
@@ -383,17 +510,21 @@ impl Writer {
         if needs_transparency {
             img.add_fixed_color(RGBA8::new(0, 0, 0, 0))?;
         }
+        for &color in fixed_colors {
+            img.add_fixed_color(color)?;
+        }
         let res = liq.quantize(&mut img)?;
         Ok((liq, res, img))
     }
 
-    fn remap(liq: Attributes, mut res: QuantizationResult, mut img: Image<'static>, background: Option<ImgRef<'_, RGBA8>>, settings: &Settings) -> CatResult<(ImgVec<u8>, Vec<RGBA8>)> {
+    fn remap(liq: Attributes, mut res: QuantizationResult, mut img: Image<'static>, background: Option<ImgRef<'_, RGBA8>>, settings: &SettingsExt) -> CatResult<(ImgVec<u8>, Vec<RGBA8>)> {
         if let Some(bg) = background {
             let (buf, width, height) = bg.to_contiguous_buf();
             img.set_background(liq.new_image(buf, width, height, 0.)?)?;
         }
 
-        res.set_dithering_level((settings.quality as f32 / 50.0 - 1.).max(0.))?;
+        let dithering_level = settings.dithering_level.unwrap_or((settings.s.quality as f32 / 50.0 - 1.).max(0.));
+        res.set_dithering_level(dithering_level)?;
 
         let (pal, pal_img) = res.remapped(&mut img)?;
         debug_assert_eq!(img.width() * img.height(), pal_img.len());
@@ -401,22 +532,31 @@ impl Writer {
         Ok((Img::new(pal_img, img.width(), img.height()), pal))
     }
 
-    fn write_frames(write_queue: Receiver<FrameMessage>, enc: &mut dyn Encoder, settings: &Settings, reporter: &mut dyn ProgressReporter) -> CatResult<()> {
+    fn write_frames(write_queue: Receiver<FrameMessage>, enc: &mut dyn Encoder, settings: &SettingsExt, reporter: &mut dyn ProgressReporter) -> CatResult<()> {
         let mut pts_in_delay_units = 0_u64;
 
         let mut n_done = 0;
+        let mut n_skipped = 0;
+        let mut n_shortened = 0;
         for FrameMessage {frame, ordinal_frame_number, end_pts, ..} in write_queue {
-            let delay = ((end_pts * 100.0).round() as u64)
-                .saturating_sub(pts_in_delay_units)
-                .min(30000) as u16;
+            let delay = settings.delay_rounding.centiseconds(end_pts)
+                .saturating_sub(pts_in_delay_units);
+            if delay > 30000 {
+                n_shortened += 1;
+            }
+            let delay = delay.min(30000) as u16;
             pts_in_delay_units += u64::from(delay);
 
             debug_assert_ne!(0, delay);
 
             // skip frames with bad pts
             if delay != 0 {
-                enc.write_frame(frame, delay, settings)?;
+                enc.write_frame(frame, delay, &settings.s)?;
+            } else {
+                n_skipped += 1;
             }
+            // frames that didn't change anything have been merged into this one
+            n_skipped += ordinal_frame_number.saturating_sub(n_done + 1);
 
             // loop to report skipped frames too
             while n_done < ordinal_frame_number {
@@ -426,6 +566,12 @@ impl Writer {
                 }
             }
         }
+        if n_skipped > 0 {
+            reporter.warning("frames_skipped", &format!("{} frames have been skipped, because they were identical to the previous frame or too short", n_skipped));
+        }
+        if n_shortened > 0 {
+            reporter.warning("delay_clamped", &format!("{} frames have been shortened to 300 seconds, the longest delay that gifski supports", n_shortened));
+        }
         if n_done == 0 {
             Err(Error::NoFrames)
         } else {
@@ -439,43 +585,44 @@ impl Writer {
     ///
     /// `ProgressReporter.increase()` is called each time a new frame is being written.
     #[allow(unused_mut)]
-    pub fn write<W: Write>(self, mut writer: W, reporter: &mut dyn ProgressReporter) -> CatResult<()> {
+    pub fn write<W: Write>(mut self, mut writer: W, reporter: &mut dyn ProgressReporter) -> CatResult<()> {
+        let comment = std::mem::take(&mut self.comment);
 
         #[cfg(feature = "gifsicle")]
         {
-            if self.settings.s.quality < 100 {
-                let mut gifsicle = encodegifsicle::Gifsicle::new(self.settings.s.gifsicle_loss(), &mut writer);
+            if self.settings.lossy_quality < 100 {
+                let mut gifsicle = encodegifsicle::Gifsicle::new(self.settings.gifsicle_loss(), &mut writer, comment);
                 return self.write_with_encoder(&mut gifsicle, reporter);
             }
         }
-        self.write_with_encoder(&mut encoderust::RustEncoder::new(writer), reporter)
+        self.write_with_encoder(&mut encoderust::RustEncoder::new(writer, comment), reporter)
     }
 
     fn write_with_encoder(mut self, encoder: &mut dyn Encoder, reporter: &mut dyn ProgressReporter) -> CatResult<()> {
         let decode_queue_recv = self.queue_iter.take().ok_or(Error::Aborted)?;
 
         let settings_ext = self.settings;
-        let settings = self.settings.s;
+        let fixed_colors = std::mem::take(&mut self.fixed_colors);
         let (quant_queue, quant_queue_recv) = crossbeam_channel::bounded(4);
         let diff_thread = thread::Builder::new().name("diff".into()).spawn(move || {
-            Self::make_diffs(decode_queue_recv, quant_queue, &settings)
+            Self::make_diffs(decode_queue_recv, quant_queue, &settings_ext)
         })?;
         let (remap_queue, remap_queue_recv) = crossbeam_channel::bounded(8);
         let quant_thread = thread::Builder::new().name("quant".into()).spawn(move || {
-            Self::quantize_frames(quant_queue_recv, remap_queue, &settings_ext)
+            Self::quantize_frames(quant_queue_recv, remap_queue, &settings_ext, &fixed_colors)
         })?;
         let (write_queue, write_queue_recv) = crossbeam_channel::bounded(6);
         let remap_thread = thread::Builder::new().name("remap".into()).spawn(move || {
-            Self::remap_frames(remap_queue_recv, write_queue, &settings)
+            Self::remap_frames(remap_queue_recv, write_queue, &settings_ext)
         })?;
-        Self::write_frames(write_queue_recv, encoder, &self.settings.s, reporter)?;
+        Self::write_frames(write_queue_recv, encoder, &self.settings, reporter)?;
         diff_thread.join().map_err(|_| Error::ThreadSend)??;
         quant_thread.join().map_err(|_| Error::ThreadSend)??;
         remap_thread.join().map_err(|_| Error::ThreadSend)??;
         Ok(())
     }
 
-    fn make_diffs(mut inputs: OrdQueueIter<DecodedImage>, quant_queue: Sender<DiffMessage>, settings: &Settings) -> CatResult<()> {
+    fn make_diffs(mut inputs: OrdQueueIter<DecodedImage>, quant_queue: Sender<DiffMessage>, settings: &SettingsExt) -> CatResult<()> {
         let (first_frame, first_frame_raw_pts) = inputs.next().transpose()?.ok_or(Error::NoFrames)?;
 
         let mut last_frame_duration = if first_frame_raw_pts > 1. / 100. {
@@ -486,7 +633,7 @@ impl Writer {
             LastFrameDuration::FrameRate(0.)
         };
 
-        let mut denoiser = Denoiser::new(first_frame.width(), first_frame.height(), settings.quality);
+        let mut denoiser = Denoiser::new(first_frame.width(), first_frame.height(), settings.motion_quality);
 
 
         let mut next_frame = Some((first_frame, first_frame_raw_pts));
@@ -505,9 +652,15 @@ impl Writer {
             let curr_frame = next_frame.take();
             next_frame = inputs.next().transpose()?;
 
-            if let Some((image, raw_pts)) = curr_frame {
+            if let Some((mut image, raw_pts)) = curr_frame {
                 ordinal_frame_number += 1;
 
+                if let Some(key) = settings.transparent_color {
+                    for px in image.rows_mut().flatten().filter(|px| **px == key) {
+                        px.a = 0;
+                    }
+                }
+
                 let pts = raw_pts - last_frame_duration.shift_every_pts_by();
                 if let LastFrameDuration::FrameRate(duration) = &mut last_frame_duration {
                     *duration = pts - last_frame_pts;
@@ -546,7 +699,7 @@ impl Writer {
         Ok(())
     }
 
-    fn quantize_frames(inputs: Receiver<DiffMessage>, remap_queue: Sender<RemapMessage>, settings: &SettingsExt) -> CatResult<()> {
+    fn quantize_frames(inputs: Receiver<DiffMessage>, remap_queue: Sender<RemapMessage>, settings: &SettingsExt, fixed_colors: &[RGBA8]) -> CatResult<()> {
         let mut inputs = inputs.into_iter().peekable();
 
         let DiffMessage {image: first_frame, ..} = inputs.peek().ok_or(Error::NoFrames)?;
@@ -589,15 +742,15 @@ impl Writer {
                 if prev_frame_keeps {
                     // if denoiser says the background didn't change, then believe it
                     // (except higher quality settings, which try to improve it every time)
-                    let bg_keep_likelyhood = (settings.s.quality.saturating_sub(80) / 4) as u32;
-                    if settings.s.fast || (settings.s.quality < 100 && (consecutive_frame_num % 5) >= bg_keep_likelyhood) {
+                    let bg_keep_likelyhood = (settings.motion_quality.saturating_sub(80) / 4) as u32;
+                    if settings.s.fast || (settings.motion_quality < 100 && (consecutive_frame_num % 5) >= bg_keep_likelyhood) {
                         image.pixels_mut().zip(&importance_map).filter(|&(_, &m)| m == 0).for_each(|(px, _)| *px = RGBA8::new(0,0,0,0));
                     }
                 }
 
                 let needs_transparency = consecutive_frame_num > 0 || (consecutive_frame_num == 0 && first_frame_has_transparency);
-                let (liq, remap, liq_image) = Self::quantize(image, &importance_map, consecutive_frame_num == 0, needs_transparency, prev_frame_keeps, settings)?;
-                let max_loss = settings.s.gifsicle_loss();
+                let (liq, remap, liq_image) = Self::quantize(image, &importance_map, consecutive_frame_num == 0, needs_transparency, prev_frame_keeps, settings, fixed_colors)?;
+                let max_loss = settings.gifsicle_loss();
                 for imp in &mut importance_map {
                     // encoding assumes rgba background looks like encoded background, which is not true for lossy
                     *imp = ((256 - (*imp) as u32) * max_loss / 256).min(255) as u8;
@@ -624,7 +777,7 @@ impl Writer {
         Ok(())
     }
 
-    fn remap_frames(inputs: Receiver<RemapMessage>, write_queue: Sender<FrameMessage>, settings: &Settings) -> CatResult<()> {
+    fn remap_frames(inputs: Receiver<RemapMessage>, write_queue: Sender<FrameMessage>, settings: &SettingsExt) -> CatResult<()> {
         let mut inputs = inputs.into_iter().peekable();
         let first_frame = inputs.peek().ok_or(Error::NoFrames)?;
         let mut screen = gif_dispose::Screen::new(first_frame.liq_image.width(), first_frame.liq_image.height(), RGBA8::new(0, 0, 0, 0), None);
@@ -9,6 +9,10 @@ pub trait ProgressReporter: Send {
 
     /// Mark the progress as done.
     fn done(&mut self, msg: &str);
+
+    /// Report a problem that didn't stop the encoding, e.g. frames that have been skipped.
+    /// `category` is a short identifier of the kind of problem.
+    fn warning(&mut self, _category: &str, _msg: &str) {}
 }
 
 /// No-op progress reporter
@@ -1,11 +1,28 @@
-use rgb::RGBA8;
-use std::ffi::CString;
+use rgb::{RGB8, RGBA8};
+use rgb::alt::BGRA8;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
 use std::mem;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use pyo3::{PyResult, exceptions};
+use pyo3::buffer::PyBuffer;
 use pyo3::prelude::*;
+use pyo3::types::PyBytes;
 use crate::c_api::*;
 
-/// Gifski(width, height, /, quality=90, fast=False, repeat=0)
+mod py_api_builder;
+mod py_api_error;
+use self::py_api_builder::PyGifskiBuilder;
+use self::py_api_error::{add_exceptions, error_code_name, error_from_code, error_with_message};
+
+/// Gifski(width, height, /, quality=90, fast=False, repeat=0, fps=None, motion_quality=None, lossy_quality=None, dither=None, resize=False, transparent_color=None, max_pixels=None, dedupe=False, max_frames=None, lossy=None, keep_frames=False, speed=1.0, fit="error", background=None, auto_rebase=False, alpha_threshold=None, delay_rounding="round")
 ///
 /// Example usage for creating a gif:
 ///     frame_duration = 1 / 24 # 24 frames per second
@@ -19,142 +36,4459 @@ use crate::c_api::*;
 ///         timestamp += frame_duration
 ///
 ///     g.finish()
-/// 
+///
+/// With fps set, the timestamps are computed from the frame number:
+///     g = Gifski(width, height, fps=24)
+///     for frame in imgs:
+///         g.add_frame(frame.convert('RGBA').tobytes())
+///
+/// If frames are added before setting an output, the gif is encoded in memory,
+/// and can be retrieved with finish_to_bytes(). An output can still be set
+/// afterwards, and then the gif encoded so far is written to it first, so the
+/// output can be set before or after adding frames.
+///
+/// Gifski can also be used as a context manager, which calls finish() at the
+/// end of the block, or discards the output if the block raised an exception:
+///     with Gifski(width, height) as g:
+///         g.set_file_output("output/path.gif")
+///         ...
+///
+/// The same settings can be set with chained calls on a GifskiBuilder.
+///
+/// The size and quality of the gif are mainly controlled by three settings:
+///  - quality sets how many colors are used in each frame's palette, and how
+///    much the frames can differ from the input,
+///  - fast makes encoding faster by spending less time on finding the palettes
+///    and on smoothing motion. It doesn't make the file smaller,
+///  - lossy sets how many artifacts the compression of the pixels may add,
+///    independently of the palettes. It makes the file smaller at the cost
+///    of grainy noise, and is the setting to use for a target file size.
+///
+/// Before any frames are added, Gifski objects can be pickled, e.g. to send
+/// them to a multiprocessing worker. All the settings returned by settings()
+/// are kept, but the unpickled object has no output or callbacks.
+///
+/// A Gifski object can be passed between threads, but it's not meant to be
+/// used by several threads at the same time. Methods that add frames or
+/// change the object raise RuntimeError when another thread is in the middle
+/// of a call on the same object, instead of waiting for it. Use a lock to
+/// share one object between threads, or add_frame_rgba_at(), which can be
+/// called from several threads at once.
+///
 /// Parameters
 /// ----------
 /// width : int
-///     positive integer, pixel width
+///     positive integer, pixel width, at most 65535
 /// height : int
-///     positive integer, pixel height
-/// quality : int
-///     integer from 1 (best compression) to 100 (best quality)
-/// fast : bool
-///     faster encoder, lower quality
+///     positive integer, pixel height, at most 65535
+/// quality : int or Preset
+///     integer from 1 (best compression) to 100 (best quality), or a Preset,
+///     which also sets motion_quality, lossy_quality and dither. The limits
+///     are also available as gifski.MIN_QUALITY and gifski.MAX_QUALITY.
+/// fast : int or bool
+///     speed level from 0 (slowest, best quality) to 3 (fastest, lower
+///     quality). The default is 1. True is the same as 3, and False as 1.
 /// repeat : int
-///     -1 for no looping, 0 for infinite looping, or n for looping n times
-#[pyclass]
+///     -1 for no looping, 0 for infinite looping, or n for looping n times,
+///     up to 32767
+/// fps : float, optional
+///     frames per second, used by add_frame() when its timestamp is omitted
+/// motion_quality : int, optional
+///     1-100, defaults to quality. Lower values reduce motion and flicker,
+///     which makes animations smaller.
+/// lossy_quality : int, optional
+///     1-100, defaults to quality. Lower values allow more noise and artifacts
+///     in lossy compression, which makes the file smaller.
+/// lossy : int, optional
+///     lossy compression strength from 0 (off) to 99 (smallest file, most
+///     noise). It's the reverse of lossy_quality, so only one of them can be
+///     given. Without either, lossy compression depends on quality.
+/// dither : bool or float, optional
+///     dithering strength from 0.0 to 1.0, or True/False for full/none.
+///     Dithering smooths gradients in photos, but adds noise to flat colors,
+///     e.g. in pixel art. By default it depends on quality.
+/// resize : bool
+///     if True, add_frame_ndarray() and add_frame_pil() scale frames of other
+///     sizes to width and height, using bilinear interpolation. Otherwise
+///     frames of other sizes are an error. Methods taking raw pixel buffers
+///     always require the exact size. Same as fit="stretch".
+/// transparent_color : (int, int, int, int), optional
+///     (r, g, b, a) color with values from 0 to 255. Pixels of exactly this
+///     color are made fully transparent, e.g. to key out a background.
+///     Frames with alpha are made fully opaque or fully transparent first,
+///     so a should usually be 255. Pixels that are already transparent
+///     stay transparent.
+/// max_pixels : int, optional
+///     if width*height is larger than this, ValueError is raised before
+///     anything is allocated. Unlimited by default.
+/// dedupe : bool
+///     if True, a frame with the same pixels as the previous frame isn't
+///     encoded, and the previous frame is displayed longer instead. The frames
+///     are compared without holding the GIL, and the number of merged frames
+///     is reported in FinishStats. Frames from add_frame_png_file() aren't
+///     compared. The encoder drops identical frames anyway, but this avoids
+///     the cost of processing them.
+/// max_frames : int, optional
+///     if this many frames have been added, add_frame_* methods raise
+///     ValueError instead of adding more. Unlimited by default.
+/// keep_frames : bool
+///     if True, a copy of every added frame is kept in memory, which is
+///     needed by finish_thumbnail(), preview_palette(), finish_to_size() and
+///     estimate_size(). It takes width*height*4 bytes per frame.
+/// speed : float
+///     playback speed. The timestamps of frames are divided by it, so e.g.
+///     2.0 makes the gif twice as fast, and 0.5 makes it slow motion. Other
+///     methods take and return the timestamps before dividing.
+/// fit : str
+///     how add_frame_ndarray() and add_frame_pil() handle frames of other
+///     sizes: "error" raises ValueError, "stretch" is the same as resize=True,
+///     and "pad" centers the frames on a canvas of the background color.
+///     With "pad", frames larger than the gif are scaled down to fit, keeping
+///     their aspect ratio, and smaller frames aren't scaled.
+/// background : (int, int, int, int), optional
+///     (r, g, b, a) color with values from 0 to 255 of the padding for
+///     fit="pad". Transparent by default.
+/// auto_rebase : bool
+///     if True, the first frame can have any timestamp, and it's subtracted
+///     from the timestamps of all frames, e.g. for absolute times of a capture.
+///     The timestamps must still increase. By default the first frame should
+///     have timestamp 0, and a later start is added to the last frame's
+///     duration. This doesn't apply to add_frame_rgba_at().
+/// alpha_threshold : int, optional
+///     0-255. If set, pixels with alpha below it are made fully transparent,
+///     and all others fully opaque, before the frames are encoded. GIF has
+///     only one transparent color, so this gives crisp edges instead of the
+///     encoder's approximation of semi-transparent pixels. The palette from
+///     preview_palette() and the frames kept for keep_frames are after it.
+/// delay_rounding : str
+///     how timestamps are converted to GIF delays, which are in hundredths of
+///     a second: "round" to the nearest (the default), "floor" down, or
+///     "nearest_even" to the nearest, with halfway cases to the even one.
+///     The end time of each frame is rounded, and its delay is the difference
+///     from the previous frame's rounded end time, so the rounding errors
+///     don't accumulate: e.g. at 30 fps the delays alternate between 3 and 4,
+///     and every frame is shown within 1/100th of a second of its timestamp.
+#[pyclass(module = "gifski")] // pickle finds the class by its module
 #[pyo3(name="Gifski")]
 struct PyGifski {
+    /// `*const GifskiHandle`, as an integer so that it can be used in `allow_threads`.
+    /// The handle locks its own state, and PyO3 doesn't allow `&mut self` methods
+    /// to run concurrently, so only the `&self` methods can share it between threads.
     _handle: usize,
+    /// Kept for reset()
+    settings: GifskiSettings,
+    extra_settings: ExtraSettings,
     width: u32,
     height: u32,
     frame_count: u32,
+    /// Frames given to the encoder, which are fewer than frame_count if they've been merged by dedupe
+    frames_sent: u32,
+    /// Limit of frame_count
+    max_frames: Option<u32>,
+    fps: Option<f64>,
+    /// Timestamps are divided by it before giving them to the encoder
+    speed: f64,
+    /// What to do with frames of other sizes, when their size is known
+    fit: Fit,
+    /// Alpha below it becomes 0, and the rest 255
+    alpha_threshold: Option<u8>,
+    /// If set, the first frame's timestamp is the origin, instead of being required to be 0
+    auto_rebase: bool,
+    /// Subtracted from the timestamps of frames. Set by the first frame with auto_rebase.
+    timestamp_origin: f64,
+    /// Frames must be added in order of their timestamps, which are after subtracting timestamp_origin
+    last_timestamp: Option<f64>,
+    /// Timestamps of all frames counted in frame_count, for frame_timestamps()
+    timestamps: Vec<f64>,
+    /// Sum of durations from add_frame_rgba_duration(), the next frame's timestamp
+    elapsed: f64,
+    /// Timestamp of the last frame from add_frame_rgba_ms(), compared exactly
+    last_timestamp_ms: Option<u64>,
+    /// The previous frame from add_frame_rgba_region(), empty until it's used
+    canvas: Vec<RGBA8>,
+    /// Set if dedupe is enabled
+    dedupe: Option<PreviousFrame>,
+    /// Every added frame, set if keep_frames is enabled
+    kept_frames: Option<Vec<KeptFrame>>,
+    /// Indices and timestamps of frames from add_frame_rgba_at(), which are counted here instead of frame_count.
+    /// It's locked, because the method doesn't borrow self mutably, so it can run on several threads.
+    indexed_frames: Mutex<BTreeMap<u32, f64>>,
+    /// Set once the handle has been passed to `gifski_finish` (which frees it)
+    finished: bool,
+    /// Set by close(), after which even reset() isn't allowed
+    closed: bool,
+    /// Set by set_file_output() or set_write_callback()
+    has_output: bool,
+    /// For reporting the file size from finish()
+    output_path: Option<PathBuf>,
+    /// The file is written by the write callback, so it's not removed by gifski on failure
+    owns_output_file: bool,
+    /// Frames are encoded as they're added, so the encoding starts here
+    created_at: Instant,
+    callbacks: Arc<Callbacks>,
+}
+
+/// Preset.LOW, Preset.BALANCED, Preset.HIGH or Preset.MAX
+///
+/// Can be given as quality to Gifski(). The presets set these values,
+/// unless they're given explicitly:
+///     Preset      quality  motion_quality  lossy_quality  dither
+///     LOW         50       40              40             0.0
+///     BALANCED    80       70              70             0.6
+///     HIGH        90       90              85             0.8
+///     MAX         100      100             100            1.0
+#[pyclass]
+#[pyo3(name="Preset")]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+enum PyGifskiPreset {
+    LOW,
+    BALANCED,
+    HIGH,
+    MAX,
+}
+
+impl PyGifskiPreset {
+    /// quality, motion_quality, lossy_quality, dithering level
+    fn values(self) -> (u8, u8, u8, f32) {
+        match self {
+            Self::LOW => (50, 40, 40, 0.0),
+            Self::BALANCED => (80, 70, 70, 0.6),
+            Self::HIGH => (90, 90, 85, 0.8),
+            Self::MAX => (100, 100, 100, 1.0),
+        }
+    }
+}
+
+/// How frames of other sizes are made to fit, in methods that know the frame size
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Fit {
+    Error,
+    Stretch,
+    /// Centered on a canvas of this color, and scaled down if needed
+    Pad(RGBA8),
+}
+
+impl Fit {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Stretch => "stretch",
+            Self::Pad(_) => "pad",
+        }
+    }
+}
+
+/// Gifski()'s fast argument
+#[derive(FromPyObject)]
+enum FastArg {
+    Bool(bool),
+    Level(i64),
+}
+
+/// Gifski()'s quality argument
+#[derive(FromPyObject)]
+enum QualityArg {
+    Preset(PyGifskiPreset),
+    Int(i64),
+}
+
+/// The last frame given to the encoder, for comparing with the next one
+#[derive(Default)]
+struct PreviousFrame {
+    pixels: Vec<RGBA8>,
+    /// Timestamp of the last frame merged into it.
+    /// The frame is added again with it in finish(), so that it's displayed until the end.
+    merged_timestamp: Option<f64>,
+}
+
+/// A copy of an added frame, for keep_frames
+struct KeptFrame {
+    pixels: Vec<RGBA8>,
+    /// As given to the encoder, after dividing by speed
+    timestamp: f64,
+}
+
+/// Copies of a frame's pixels that are kept after adding it
+struct FrameCopies<'a> {
+    /// For dedupe
+    previous: Option<&'a mut PreviousFrame>,
+    /// For keep_frames
+    kept: Option<&'a mut Vec<KeptFrame>>,
+    /// The pixels are copied to change their alpha
+    alpha_threshold: Option<u8>,
+}
+
+/// Adds pixels that gifski's C API takes without converting them to RGBA,
+/// with (handle, frame_number, width, height, pixels, timestamp)
+type AddFrameDirect = unsafe fn(usize, u32, u32, u32, &[u8], f64) -> GifskiError;
+
+/// Settings that the C API sets with separate functions after `gifski_new`
+#[derive(Clone)]
+struct ExtraSettings {
+    /// 0-3, `fast` in `GifskiSettings` is the same as 3
+    fast_level: u8,
+    motion_quality: u8,
+    lossy_quality: u8,
+    dithering_level: Option<f32>,
+    fixed_palette: Vec<RGBA8>,
+    transparent_color: Option<RGBA8>,
+    comment: Option<CString>,
+    /// Index in `DELAY_ROUNDINGS`, the value for `gifski_set_delay_rounding`
+    delay_rounding: u8,
+}
+
+/// Settings of the Python wrapper, which change the frames before they're given to the C API
+#[derive(Copy, Clone)]
+struct FrameOptions {
+    fps: Option<f64>,
+    fit: Fit,
+    dedupe: bool,
+    max_frames: Option<u32>,
+    keep_frames: bool,
+    speed: f64,
+    auto_rebase: bool,
+    alpha_threshold: Option<u8>,
+}
+
+impl Default for FrameOptions {
+    fn default() -> Self {
+        Self {
+            fps: None,
+            fit: Fit::Error,
+            dedupe: false,
+            max_frames: None,
+            keep_frames: false,
+            speed: 1.0,
+            auto_rebase: false,
+            alpha_threshold: None,
+        }
+    }
+}
+
+/// State used by callbacks running on gifski's writer thread.
+///
+/// It's given to C as `user_data`, so it must outlive the handle.
+///
+/// The callbacks take the GIL whenever they call Python, so every C call that waits
+/// for the writer thread must be made with the GIL released. To avoid deadlocks,
+/// the mutexes must never be held while waiting for the GIL.
+#[derive(Default)]
+struct Callbacks {
+    aborted: AtomicBool,
+    frames_written: AtomicU32,
+    /// Frames not encoded, because of dedupe
+    frames_merged: AtomicU32,
+    bytes_written: AtomicU64,
+    /// Used by finish_iter()
+    progress_sender: Mutex<Option<crossbeam_channel::Sender<u32>>>,
+    progress: Mutex<Option<PyObject>>,
+    /// tqdm bar from finish(progress=True)
+    progress_bar: Mutex<Option<PyObject>>,
+    error_message: Mutex<Option<PyObject>>,
+    /// For get_last_error()
+    last_error_message: Mutex<Option<String>>,
+    /// Message logged since the last exception, to be added to the next one
+    unreported_error_message: Mutex<Option<String>>,
+    warning: Mutex<Option<PyObject>>,
+    output: Mutex<Option<Output>>,
+    /// From add_write_callback(), written after the output
+    tee_writers: Mutex<Vec<PyObject>>,
+    /// Exception raised by a callback, to be re-raised from finish()
+    error: Mutex<Option<PyErr>>,
+}
+
+/// Returned by finish()
+#[pyclass]
+#[pyo3(name="FinishStats")]
+struct PyFinishStats {
+    /// Number of frames processed by the encoder
+    #[pyo3(get)]
+    frames_written: u32,
+    /// Number of frames merged into the previous frame with dedupe=True
+    #[pyo3(get)]
+    frames_merged: u32,
+    /// Size of the gif in bytes, or None if unknown
+    #[pyo3(get)]
+    output_bytes: Option<u64>,
+    /// Seconds from creating the Gifski object until the gif was finished
+    #[pyo3(get)]
+    duration: f64,
+    /// False if the encoding has been aborted, and the output is incomplete or removed
+    #[pyo3(get)]
+    complete: bool,
+}
+
+impl PyFinishStats {
+    fn new(callbacks: &Callbacks, output_path: Option<&Path>, created_at: Instant) -> Self {
+        let output_bytes = match output_path {
+            Some(path) => std::fs::metadata(path).ok().map(|meta| meta.len()),
+            None => Some(callbacks.bytes_written.load(Ordering::SeqCst)),
+        };
+        Self {
+            frames_written: callbacks.frames_written.load(Ordering::SeqCst),
+            frames_merged: callbacks.frames_merged.load(Ordering::SeqCst),
+            output_bytes,
+            duration: created_at.elapsed().as_secs_f64(),
+            complete: !callbacks.aborted.load(Ordering::SeqCst),
+        }
+    }
 }
 
 #[pymethods]
-impl PyGifski {
+impl PyFinishStats {
+    fn __repr__(&self) -> String {
+        let output_bytes = self.output_bytes.map_or_else(|| "None".to_string(), |b| b.to_string());
+        format!("FinishStats(frames_written={}, frames_merged={}, output_bytes={}, duration={:.3}, complete={})", self.frames_written, self.frames_merged, output_bytes, self.duration, if self.complete { "True" } else { "False" })
+    }
+}
 
-    #[new]
-    #[args(quality=90, fast=false, repeat=0)]
-    unsafe fn new(width: u32, height: u32, quality: u8, fast: bool, repeat: i16) -> PyResult<Self> {
-        if width == 0 || height == 0 {
-            return Err(exceptions::PyValueError::new_err("width and height must be greater than 0"));
+/// Given to the callback of set_warning_callback()
+#[pyclass]
+#[pyo3(name="EncoderWarning")]
+struct PyEncoderWarning {
+    /// Kind of the problem, e.g. "frames_skipped" or "delay_clamped"
+    #[pyo3(get)]
+    category: String,
+    /// Explanation for humans
+    #[pyo3(get)]
+    message: String,
+}
+
+#[pymethods]
+impl PyEncoderWarning {
+    fn __repr__(&self) -> String {
+        format!("EncoderWarning(category={:?}, message={:?})", self.category, self.message)
+    }
+}
+
+/// Destination for `write_trampoline`
+enum Output {
+    /// Python file-like object
+    Writer(PyObject),
+    /// The gif for finish_to_bytes()
+    Memory(Vec<u8>),
+    /// File descriptor owned by the caller, so it's never closed
+    #[cfg(unix)]
+    Fd(mem::ManuallyDrop<std::fs::File>),
+    /// Chunks for finish_chunks(), bounded so that the writer waits for the consumer
+    Chunks(crossbeam_channel::Sender<Vec<u8>>),
+    /// File opened by set_file_output(), when there are `tee_writers` too
+    File(std::fs::File),
+}
+
+impl Callbacks {
+    /// Keeps the first error, since the following ones are likely caused by it
+    fn set_error(&self, err: PyErr) {
+        self.error.lock().unwrap().get_or_insert(err);
+    }
+
+    /// Writes the gif encoded in memory to the new output, and makes it the output.
+    /// The lock is held meanwhile, so the writer thread can't write anything in between.
+    fn replace_memory_output(&self, mut new_output: Output) -> GifskiError {
+        let mut output = self.output.lock().unwrap();
+        let buffered = match &mut *output {
+            Some(Output::Memory(buf)) => mem::take(buf),
+            _ => return GifskiError::INVALID_STATE,
+        };
+        let res = match &mut new_output {
+            _ if buffered.is_empty() => GifskiError::OK,
+            #[cfg(unix)]
+            Output::Fd(file) => write_to_file(file, &buffered),
+            Output::File(file) => write_to_file(file, &buffered),
+            Output::Writer(writer) => write_to_python(self, writer.clone(), &buffered),
+            Output::Memory(_) | Output::Chunks(_) => GifskiError::INVALID_STATE,
+        };
+        *output = Some(if res == GifskiError::OK { new_output } else { Output::Memory(buffered) });
+        res
+    }
+
+    /// Exception for the error code, with the message that gifski has logged about it
+    fn error(&self, code: GifskiError) -> PyErr {
+        let message = self.unreported_error_message.lock().unwrap().take();
+        error_with_message(code, message.as_deref())
+    }
+
+    /// The callbacks won't be called any more after `gifski_finish`, and they could be keeping the encoder alive
+    fn clear(&self) {
+        *self.progress.lock().unwrap() = None;
+        *self.error_message.lock().unwrap() = None;
+        *self.warning.lock().unwrap() = None;
+        self.tee_writers.lock().unwrap().clear();
+        let mut output = self.output.lock().unwrap();
+        if !matches!(&*output, Some(Output::Memory(_))) {
+            *output = None;
+        }
+    }
+}
+
+/// Returned by finish_iter()
+#[pyclass]
+#[pyo3(name="FinishIter")]
+struct PyFinishIter {
+    callbacks: Arc<Callbacks>,
+    progress: crossbeam_channel::Receiver<u32>,
+    /// Runs `gifski_finish`
+    thread: Option<thread::JoinHandle<GifskiError>>,
+    frame_count: u32,
+}
+
+#[pymethods]
+impl PyFinishIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<f64>> {
+        let progress = &self.progress;
+        // the trampolines need the GIL
+        if let Some(frames_written) = py.allow_threads(|| progress.recv().ok()) {
+            return Ok(Some((frames_written as f64 / self.frame_count.max(1) as f64).min(1.)));
+        }
+
+        // the channel is disconnected when gifski_finish returns
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(None),
+        };
+        let res = py.allow_threads(|| thread.join()).unwrap_or(GifskiError::THREAD_LOST);
+        self.callbacks.clear();
+        if let Some(err) = self.callbacks.error.lock().unwrap().take() {
+            return Err(err);
+        }
+        if res != GifskiError::OK {
+            return Err(self.callbacks.error(res));
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for PyFinishIter {
+    fn drop(&mut self) {
+        // an unfinished iteration still has to write the whole gif
+        if let Some(thread) = self.thread.take() {
+            Python::with_gil(|py| {
+                let _ = py.allow_threads(|| thread.join());
+            });
+            self.callbacks.clear();
+        }
+    }
+}
+
+/// Returned by finish_chunks()
+#[pyclass]
+#[pyo3(name="FinishChunks")]
+struct PyFinishChunks {
+    callbacks: Arc<Callbacks>,
+    /// Written before finish_chunks() was called
+    buffered: Vec<u8>,
+    chunks: crossbeam_channel::Receiver<Vec<u8>>,
+    /// Runs `gifski_finish`
+    thread: Option<thread::JoinHandle<GifskiError>>,
+}
+
+#[pymethods]
+impl PyFinishChunks {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        if !self.buffered.is_empty() {
+            let buffered = mem::take(&mut self.buffered);
+            return Ok(Some(PyBytes::new(py, &buffered).into()));
+        }
+        let chunks = &self.chunks;
+        if let Some(chunk) = py.allow_threads(|| chunks.recv().ok()) {
+            return Ok(Some(PyBytes::new(py, &chunk).into()));
+        }
+
+        // the channel is disconnected when the output is cleared after gifski_finish
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(None),
+        };
+        let res = py.allow_threads(|| thread.join()).unwrap_or(GifskiError::THREAD_LOST);
+        if let Some(err) = self.callbacks.error.lock().unwrap().take() {
+            return Err(err);
+        }
+        if res != GifskiError::OK {
+            return Err(self.callbacks.error(res));
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for PyFinishChunks {
+    fn drop(&mut self) {
+        // nobody is going to read the rest of the gif
+        if let Some(thread) = self.thread.take() {
+            self.callbacks.aborted.store(true, Ordering::SeqCst);
+            // makes the writer's sends fail
+            self.chunks = crossbeam_channel::never();
+            Python::with_gil(|py| {
+                let _ = py.allow_threads(|| thread.join());
+            });
+        }
+    }
+}
+
+unsafe extern "C" fn progress_trampoline(user_data: *mut c_void) -> c_int {
+    let callbacks = &*(user_data as *const Callbacks);
+    if callbacks.aborted.load(Ordering::SeqCst) {
+        return 0;
+    }
+    let frames_written = callbacks.frames_written.fetch_add(1, Ordering::SeqCst) + 1;
+    if let Some(sender) = &*callbacks.progress_sender.lock().unwrap() {
+        let _ = sender.send(frames_written);
+    }
+
+    // always lock the GIL before the mutexes, same as the Python side does
+    let keep_going = Python::with_gil(|py| {
+        let bar = callbacks.progress_bar.lock().unwrap().as_ref().map(|bar| bar.clone_ref(py));
+        if let Some(bar) = bar {
+            // it's only for show, so it can't stop the encoding
+            let _ = bar.call_method1(py, "update", (1,));
+        }
+        let callback = match &*callbacks.progress.lock().unwrap() {
+            Some(callback) => callback.clone_ref(py),
+            None => return true,
+        };
+        match callback.call1(py, (frames_written,)).and_then(|res| res.as_ref(py).is_true()) {
+            Ok(keep_going) => keep_going,
+            Err(err) => {
+                callbacks.set_error(err);
+                false
+            },
         }
-        if quality < 1 || quality > 100 {
-            return Err(exceptions::PyValueError::new_err("quality must be between 1 and 1000"));
+    });
+    if !keep_going {
+        callbacks.aborted.store(true, Ordering::SeqCst);
+        return 0;
+    }
+    1
+}
+
+unsafe extern "C" fn error_message_trampoline(message: *const c_char, user_data: *mut c_void) {
+    let callbacks = &*(user_data as *const Callbacks);
+    let message = CStr::from_ptr(message).to_string_lossy();
+    *callbacks.last_error_message.lock().unwrap() = Some(message.to_string());
+    *callbacks.unreported_error_message.lock().unwrap() = Some(message.to_string());
+
+    Python::with_gil(|py| {
+        let callback = match &*callbacks.error_message.lock().unwrap() {
+            Some(callback) => callback.clone_ref(py),
+            None => {
+                eprintln!("{}", message);
+                return;
+            },
+        };
+        if let Err(err) = callback.call1(py, (message.as_ref(),)) {
+            // there's nowhere to raise it from
+            err.print(py);
+        }
+    });
+}
+
+unsafe extern "C" fn warning_trampoline(category: *const c_char, message: *const c_char, user_data: *mut c_void) {
+    let callbacks = &*(user_data as *const Callbacks);
+    let warning = PyEncoderWarning {
+        category: CStr::from_ptr(category).to_string_lossy().into_owned(),
+        message: CStr::from_ptr(message).to_string_lossy().into_owned(),
+    };
+
+    Python::with_gil(|py| {
+        let callback = match &*callbacks.warning.lock().unwrap() {
+            Some(callback) => callback.clone_ref(py),
+            None => return,
+        };
+        if let Err(err) = callback.call1(py, (warning,)) {
+            // there's nowhere to raise it from
+            err.print(py);
+        }
+    });
+}
+
+unsafe extern "C" fn write_trampoline(buffer_length: usize, buffer: *const u8, user_data: *mut c_void) -> c_int {
+    let callbacks = &*(user_data as *const Callbacks);
+    let data = if buffer_length > 0 { slice::from_raw_parts(buffer, buffer_length) } else { &[][..] };
+
+    let res = write_output(callbacks, data);
+    if res != GifskiError::OK {
+        return res as c_int;
+    }
+    callbacks.bytes_written.fetch_add(buffer_length as u64, Ordering::SeqCst);
+
+    // the mutex can't be held while waiting for the GIL
+    let tee_writers = callbacks.tee_writers.lock().unwrap().clone();
+    for writer in tee_writers {
+        let res = write_to_python(callbacks, writer, data);
+        if res != GifskiError::OK {
+            return res as c_int;
         }
-        if repeat < -1 {
-            return Err(exceptions::PyValueError::new_err("repeat must be -1, 0, or positive"));
+    }
+    GifskiError::OK as c_int
+}
+
+/// Writes to the output set with set_*_output() or set_write_callback()
+fn write_output(callbacks: &Callbacks, data: &[u8]) -> GifskiError {
+    // the mutex can't be held while waiting for the GIL
+    let writer = match &mut *callbacks.output.lock().unwrap() {
+        Some(Output::Memory(buf)) => {
+            buf.extend_from_slice(data);
+            return GifskiError::OK;
+        },
+        #[cfg(unix)]
+        Some(Output::Fd(file)) => return write_to_file(file, data),
+        Some(Output::File(file)) => return write_to_file(file, data),
+        Some(Output::Chunks(sender)) => Err(sender.clone()),
+        Some(Output::Writer(writer)) => Ok(writer.clone()),
+        None => return GifskiError::INVALID_STATE,
+    };
+    match writer {
+        Ok(writer) => write_to_python(callbacks, writer, data),
+        Err(_) if data.is_empty() => GifskiError::OK,
+        // fails when the FinishChunks iterator has been dropped
+        Err(sender) => match sender.send(data.to_vec()) {
+            Ok(()) => GifskiError::OK,
+            Err(_) => GifskiError::ABORTED,
+        },
+    }
+}
+
+/// Empty `data` means flush
+fn write_to_file(file: &mut std::fs::File, data: &[u8]) -> GifskiError {
+    use std::io::Write;
+    let res = if !data.is_empty() { file.write_all(data) } else { file.flush() };
+    match res {
+        Ok(()) => GifskiError::OK,
+        Err(err) => err.kind().into(),
+    }
+}
+
+/// Exceptions are kept in the callbacks, to be re-raised from finish()
+fn write_to_python(callbacks: &Callbacks, writer: PyObject, data: &[u8]) -> GifskiError {
+    let res = Python::with_gil(move |py| -> PyResult<GifskiError> {
+        let writer = writer.into_ref(py);
+        if data.is_empty() {
+            if writer.hasattr("flush")? {
+                writer.call_method0("flush")?;
+            }
+            return Ok(GifskiError::OK);
+        }
+        let bytes = PyBytes::new(py, data);
+        // io.RawIOBase may write less than requested, other writers may return None
+        match writer.call_method1("write", (bytes,))?.extract::<Option<usize>>()? {
+            Some(written) if written < data.len() => Ok(GifskiError::WRITE_ZERO),
+            _ => Ok(GifskiError::OK),
         }
+    });
+    match res {
+        Ok(res) => res,
+        Err(err) => {
+            callbacks.set_error(err);
+            GifskiError::OTHER
+        },
+    }
+}
 
+#[pymethods]
+impl PyGifski {
+
+    #[new]
+    #[allow(clippy::too_many_arguments)]
+    #[args(quality="QualityArg::Int(90)", fast="FastArg::Bool(false)", repeat=0, fps="None", motion_quality="None", lossy_quality="None", dither="None", resize=false, transparent_color="None", max_pixels="None", dedupe=false, max_frames="None", lossy="None", keep_frames=false, speed="1.0", fit="\"error\"", background="None", auto_rebase=false, alpha_threshold="None", delay_rounding="\"round\"")]
+    unsafe fn new(width: u32, height: u32, quality: QualityArg, fast: FastArg, repeat: i64, fps: Option<f64>, motion_quality: Option<i64>, lossy_quality: Option<i64>, dither: Option<&PyAny>, resize: bool, transparent_color: Option<(i64, i64, i64, i64)>, max_pixels: Option<u64>, dedupe: bool, max_frames: Option<u32>, lossy: Option<i64>, keep_frames: bool, speed: f64, fit: &str, background: Option<(i64, i64, i64, i64)>, auto_rebase: bool, alpha_threshold: Option<i64>, delay_rounding: &str) -> PyResult<Self> {
+        check_max_pixels(width, height, max_pixels)?;
+        let lossy_quality = match (lossy_quality, lossy) {
+            (Some(_), Some(_)) => return Err(exceptions::PyValueError::new_err("lossy and lossy_quality can't be used together")),
+            (Some(q), None) => Some(quality_arg(q, "lossy_quality")?),
+            (None, lossy) => lossy.map(lossy_arg).transpose()?,
+        };
+        let (quality, preset_motion_quality, preset_lossy_quality, preset_dithering_level) = resolve_quality(quality)?;
+        let fast_level = fast_level(fast)?;
+        let fit = match (fit_arg(fit, background.map(color_arg).transpose()?)?, resize) {
+            (fit, false) | (fit @ Fit::Stretch, true) => fit,
+            (Fit::Error, true) => Fit::Stretch,
+            (Fit::Pad(_), true) => return Err(exceptions::PyValueError::new_err("resize=True can't be used with fit=\"pad\"")),
+        };
+        let extra_settings = ExtraSettings {
+            fast_level,
+            motion_quality: motion_quality.map(|q| quality_arg(q, "motion_quality")).transpose()?
+                .or(preset_motion_quality)
+                .unwrap_or(quality),
+            lossy_quality: lossy_quality
+                .or(preset_lossy_quality)
+                .unwrap_or(quality),
+            dithering_level: dither.map(dithering_level).transpose()?.or(preset_dithering_level),
+            fixed_palette: Vec::new(),
+            transparent_color: transparent_color.map(color_arg).transpose()?,
+            comment: None,
+            delay_rounding: delay_rounding_arg(delay_rounding)?,
+        };
         let settings = GifskiSettings {
-            width, height, quality, fast, repeat,
+            width, height, quality, fast: fast_level == 3, repeat: repeat_arg(repeat)?,
+        };
+        let options = FrameOptions {
+            fps: fps.map(fps_arg).transpose()?,
+            fit,
+            dedupe,
+            max_frames: max_frames.map(max_frames_arg).transpose()?,
+            keep_frames,
+            speed: speed_arg(speed)?,
+            auto_rebase,
+            alpha_threshold: alpha_threshold.map(alpha_threshold_arg).transpose()?,
         };
+        Self::with_settings(settings, extra_settings, options)
+    }
 
-        Ok(PyGifski {
-            _handle: gifski_new(&settings) as usize,
-            width,
-            height,
-            frame_count: 0,
-        })
+    /// Pixel width of the frames
+    #[getter]
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Pixel height of the frames
+    #[getter]
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of frames added so far
+    #[getter]
+    fn frame_count(&self) -> u32 {
+        // only one of them is used
+        self.frame_count + self.indexed_frames.lock().unwrap().len() as u32
+    }
+
+    /// Timestamps of the frames added so far, in seconds, as a new list.
+    ///
+    /// They're the timestamps given to add_frame_* methods, or computed from
+    /// fps or durations, before dividing by speed. With auto_rebase, they're
+    /// relative to the first frame. Frames merged by dedupe are included.
+    /// For add_frame_rgba_at(), they're in the order of the indices.
+    #[pyo3(text_signature = "(self, /)")]
+    fn frame_timestamps(&self) -> Vec<f64> {
+        let indexed_frames = self.indexed_frames.lock().unwrap();
+        if !indexed_frames.is_empty() {
+            return indexed_frames.values().copied().collect();
+        }
+        self.timestamps.clone()
+    }
+
+    /// The settings in effect, after applying presets and defaults.
+    ///
+    /// Returns a dict with keys width, height, quality, fast (the level 0-3), repeat,
+    /// motion_quality, lossy_quality, dither (None if it depends on quality),
+    /// fps (None if not set), resize, fit, background (None unless fit is "pad"),
+    /// palette (a list of (r, g, b, a) tuples, or None if set_palette() hasn't
+    /// been called), transparent_color (None if not set), comment (None if
+    /// set_comment() hasn't been called), dedupe, max_frames (None if not set),
+    /// keep_frames, speed, auto_rebase, alpha_threshold (None if not set) and
+    /// delay_rounding.
+    ///
+    /// The number of threads isn't included, because it's set for all Gifski
+    /// objects with set_num_threads().
+    #[pyo3(text_signature = "(self, /)")]
+    fn settings<'py>(&self, py: Python<'py>) -> PyResult<&'py pyo3::types::PyDict> {
+        let GifskiSettings { width, height, quality, repeat, .. } = self.settings;
+        let extra = &self.extra_settings;
+        let dict = pyo3::types::PyDict::new(py);
+        dict.set_item("width", width)?;
+        dict.set_item("height", height)?;
+        dict.set_item("quality", quality)?;
+        dict.set_item("fast", extra.fast_level)?;
+        dict.set_item("repeat", repeat)?;
+        dict.set_item("motion_quality", extra.motion_quality)?;
+        dict.set_item("lossy_quality", extra.lossy_quality)?;
+        dict.set_item("dither", extra.dithering_level)?;
+        dict.set_item("fps", self.fps)?;
+        dict.set_item("resize", self.fit == Fit::Stretch)?;
+        dict.set_item("fit", self.fit.name())?;
+        let background = match self.fit {
+            Fit::Pad(c) => Some((c.r, c.g, c.b, c.a)),
+            _ => None,
+        };
+        dict.set_item("background", background)?;
+        let palette = if extra.fixed_palette.is_empty() { None } else {
+            Some(extra.fixed_palette.iter().map(|c| (c.r, c.g, c.b, c.a)).collect::<Vec<_>>())
+        };
+        dict.set_item("palette", palette)?;
+        dict.set_item("transparent_color", extra.transparent_color.map(|c| (c.r, c.g, c.b, c.a)))?;
+        dict.set_item("comment", extra.comment.as_ref().map(|c| c.as_bytes().iter().map(|&b| b as char).collect::<String>()))?;
+        dict.set_item("dedupe", self.dedupe.is_some())?;
+        dict.set_item("max_frames", self.max_frames)?;
+        dict.set_item("keep_frames", self.kept_frames.is_some())?;
+        dict.set_item("speed", self.speed)?;
+        dict.set_item("auto_rebase", self.auto_rebase)?;
+        dict.set_item("alpha_threshold", self.alpha_threshold)?;
+        dict.set_item("delay_rounding", DELAY_ROUNDINGS[extra.delay_rounding as usize])?;
+        Ok(dict)
     }
 
     /// Set the gif output destination to the given file path.
     ///
     /// This method should only be called once on a Gifski object.
+    /// Only one of set_file_output() or set_write_callback() can be used.
     ///
-    /// For a complete list of errors, see the GifskiError enum here:
+    /// It can be called before or after adding frames. Frames added before it
+    /// have been encoded in memory, and that part of the gif is written to the
+    /// file first.
+    ///
+    /// Errors are raised as subclasses of GifskiError, one for each value of
+    /// the GifskiError enum here:
     ///     https://github.com/synthbot-anon/ImageOptim-gifski/blob/main/gifski.h
     ///
     /// Common errors:
-    ///     INVALID_STATE: the output might have already been set for this object.
-    ///     NOT_FOUND: the target directory doesn't exist.
-    ///     PERMISSION_DENIED: the target file is not writable.
-    ///     ALREADY_EXISTS: the target file already exists.
+    ///     GifskiInvalidState: the output might have already been set for this object.
+    ///     GifskiNotFound: the target directory doesn't exist.
+    ///     GifskiPermissionDenied: the target file is not writable.
+    ///     GifskiAlreadyExists: the target file already exists, and overwrite is False.
     ///
     /// Parameters
     /// ----------
-    /// destination : str
+    /// destination : str or os.PathLike
     ///     File path for writing the output gif.
-    #[pyo3(text_signature = "(self, destination, /)")]
-    unsafe fn set_file_output(&self, destination: String) -> PyResult<()> {
+    /// overwrite : bool
+    ///     If True (the default), an existing file is truncated and replaced.
+    ///     If False, it's left unchanged, and GifskiAlreadyExists is raised. The
+    ///     existence is checked when the file is opened, so there's no race with
+    ///     other processes.
+    #[args(overwrite=true)]
+    #[pyo3(text_signature = "(self, destination, /, overwrite=True)")]
+    unsafe fn set_file_output(&mut self, destination: PathBuf, overwrite: bool) -> PyResult<()> {
+        self.check_not_finished()?;
         let handle = self._handle as *const GifskiHandle;
+        let c_path = path_to_cstring(&destination).map_err(exceptions::PyValueError::new_err)?;
 
-        // make this mutable so we can get the pointer without deallocating
-        let mut c_str = CString::new(destination);
-        let ptr = c_str.as_mut().unwrap().as_ptr();
-
+        if self.is_encoding_in_memory() || !self.callbacks.tee_writers.lock().unwrap().is_empty() {
+            return self.set_file_output_with_tee(destination, overwrite);
+        }
 
-        let success = gifski_set_file_output(handle, ptr);
-        if success as u8 == 0 {
+        self.install_progress_callback();
+        let success = if overwrite {
+            gifski_set_file_output(handle, c_path.as_ptr())
+        } else {
+            gifski_set_new_file_output(handle, c_path.as_ptr())
+        };
+        if success == GifskiError::OK {
+            self.has_output = true;
+            self.output_path = Some(destination);
             return Ok(());
         }
 
-        Err(exceptions::PyException::new_err(success.to_string()))
+        Err(self.callbacks.error(success))
     }
 
-    /// Specify a new gif frame using a pixel buffer.
+    /// Change the repeat setting given when creating the Gifski object.
     ///
-    /// Example for getting a pixel buffer:
-    ///     from PIL import Image
-    ///     image = Image.open(image_path, mode='r')
-    ///     pixels = image.convert('RGBA').tobytes()
+    /// This must be called before the output is set, and before any frames are
+    /// added, otherwise GifskiInvalidState is raised.
     ///
     /// Parameters
     /// ----------
-    /// pixels : bytes
-    ///     RGBA pixel data, 4 bytes per pixel. The number of pixels must match the
-    ///     width and height provided when creating the Gifski object.
-    #[pyo3(text_signature = "(self, pixels, timestamp, /)")]
-    unsafe fn add_frame_rgba(&mut self, pixels: &[u8], timestamp: f64) -> PyResult<()> {
-        let handle = self._handle as *const GifskiHandle;
-        if pixels.len() % 4 != 0 {
-            return Err(exceptions::PyValueError::new_err("pixels must be in RGBA format, 4 bytes per pixel"));
+    /// repeat : int
+    ///     -1 for no looping, 0 for infinite looping, or n for looping n times,
+    ///     up to 32767
+    #[pyo3(text_signature = "(self, repeat, /)")]
+    unsafe fn set_repeat(&mut self, repeat: i64) -> PyResult<()> {
+        self.check_not_finished()?;
+        let repeat = repeat_arg(repeat)?;
+        let success = gifski_set_repeat(self._handle as *const GifskiHandle, repeat);
+        if success == GifskiError::OK {
+            self.settings.repeat = repeat;
+            return Ok(());
         }
-        if self.width * self.height * 4 != pixels.len() as u32 {
-            return Err(exceptions::PyValueError::new_err("pixel width*height doesn't match the width*height used during construction"));
+
+        Err(self.callbacks.error(success))
+    }
+
+    /// Make the gif loop forever. Same as set_repeat(0).
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn loop_forever(&mut self) -> PyResult<()> {
+        self.set_repeat(0)
+    }
+
+    /// Make the gif play only once. Same as set_repeat(-1).
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn no_loop(&mut self) -> PyResult<()> {
+        self.set_repeat(-1)
+    }
+
+    /// Make the gif repeat count times after playing once. Same as set_repeat(count).
+    ///
+    /// Parameters
+    /// ----------
+    /// count : int
+    ///     from 1 to 32767
+    #[pyo3(text_signature = "(self, count, /)")]
+    unsafe fn loop_n(&mut self, count: i64) -> PyResult<()> {
+        if count < 1 {
+            return Err(exceptions::PyValueError::new_err("count must be at least 1, use loop_forever() or no_loop() otherwise"));
         }
-        if self.frame_count > 0 && timestamp == 0.0 {
-            return Err(exceptions::PyValueError::new_err("only the first frame's timestamp is allowed to be 0"));
+        self.set_repeat(count)
+    }
+
+    /// True unless looping has been disabled with repeat=-1 or no_loop()
+    #[getter]
+    fn is_looping(&self) -> bool {
+        self.settings.repeat >= 0
+    }
+
+    /// Use only the given colors in all frames, instead of choosing a palette for each frame.
+    ///
+    /// This gives consistent colors across frames and across gifs encoded with
+    /// the same palette. One palette entry is needed for transparency in most
+    /// animations, so at most 255 colors can be used then.
+    ///
+    /// This must be called before the output is set, and before any frames are
+    /// added, otherwise GifskiInvalidState is raised.
+    ///
+    /// Parameters
+    /// ----------
+    /// colors : list of (int, int, int, int) tuples
+    ///     Up to 256 colors as (r, g, b, a) tuples with values from 0 to 255.
+    #[pyo3(text_signature = "(self, colors, /)")]
+    unsafe fn set_palette(&mut self, colors: Vec<(u8, u8, u8, u8)>) -> PyResult<()> {
+        self.check_not_finished()?;
+        let colors = palette_arg(colors)?;
+        let success = gifski_set_fixed_palette(self._handle as *const GifskiHandle, colors.as_ptr(), colors.len() as u32);
+        if success == GifskiError::OK {
+            self.extra_settings.fixed_palette = colors;
+            return Ok(());
         }
 
-        let buffer = mem::transmute::<*const u8, *const RGBA8>(pixels.as_ptr());
-        let success = gifski_add_frame_rgba(handle, self.frame_count, self.width, self.height, buffer, timestamp);
-        if success as u8 != 0 {
-            return Err(exceptions::PyException::new_err(success.to_string()));
+        Err(self.callbacks.error(success))
+    }
+
+    /// Embed a comment in the gif, e.g. the name and version of the tool that made it.
+    ///
+    /// The comment is stored in a comment extension, which is ignored by viewers.
+    /// An empty string removes the comment.
+    ///
+    /// This must be called before the output is set, and before any frames are
+    /// added, otherwise GifskiInvalidState is raised.
+    ///
+    /// Parameters
+    /// ----------
+    /// text : str
+    ///     Up to 65535 characters. The GIF spec expects ASCII, and characters
+    ///     outside Latin-1 raise ValueError.
+    #[pyo3(text_signature = "(self, text, /)")]
+    unsafe fn set_comment(&mut self, text: &str) -> PyResult<()> {
+        self.check_not_finished()?;
+        let comment = comment_bytes(text).map_err(exceptions::PyValueError::new_err)?;
+        let success = gifski_set_comment(self._handle as *const GifskiHandle, comment.as_ptr());
+        if success == GifskiError::OK {
+            self.extra_settings.comment = if text.is_empty() { None } else { Some(comment) };
+            return Ok(());
         }
 
-        self.frame_count += 1;
-        return Ok(());
+        Err(self.callbacks.error(success))
     }
 
-    /// Finalize the gif and write the output.
+    /// Set the gif output destination to a writable file-like object.
     ///
-    /// No further methods should be called on this object after calling finish().
-    #[pyo3(text_signature = "(self, /)")]
-    unsafe fn finish(&self) -> PyResult<()> {
-        let handle = self._handle as *const GifskiHandle;
-        let success = gifski_finish(handle);
-        if success as u8 == 0 {
+    /// The gif is written as it's being encoded, by calling writer.write(b)
+    /// with bytes. writer.flush() is called too, if the writer has it.
+    /// Exceptions raised by the writer stop the encoding, and are re-raised from finish().
+    ///
+    /// This method should only be called once on a Gifski object.
+    /// Only one of set_file_output() or set_write_callback() can be used.
+    /// Like set_file_output(), it can be called after adding frames, and then
+    /// the part of the gif encoded so far is written right away.
+    ///
+    /// Example for encoding in memory:
+    ///     buffer = io.BytesIO()
+    ///     g.set_write_callback(buffer)
+    ///
+    /// Parameters
+    /// ----------
+    /// writer : file-like object
+    ///     Object with a write() method accepting bytes, such as io.BytesIO.
+    #[pyo3(text_signature = "(self, writer, /)")]
+    unsafe fn set_write_callback(&mut self, py: Python<'_>, writer: PyObject) -> PyResult<()> {
+        if !writer.as_ref(py).hasattr("write")? {
+            return Err(exceptions::PyTypeError::new_err("writer must have a write() method"));
+        }
+        self.check_not_finished()?;
+        if !self.can_set_output() {
+            return Err(error_from_code(GifskiError::INVALID_STATE));
+        }
+
+        let success = self.start_output(Output::Writer(writer));
+        if success == GifskiError::OK {
+            self.has_output = true;
             return Ok(());
         }
 
-        return Err(exceptions::PyException::new_err(success.to_string()));
+        // raised by the writer while writing the gif encoded so far
+        if let Some(err) = self.callbacks.error.lock().unwrap().take() {
+            return Err(err);
+        }
+        Err(self.callbacks.error(success))
     }
-}
 
-#[pymodule]
-fn gifski(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_class::<PyGifski>()?;
-    Ok(())
+    /// Also write the gif to another file-like object.
+    ///
+    /// The writer gets the same data as the output, after it has been written
+    /// to the output. It works with any output, so the gif can be e.g. saved
+    /// to a file and hashed at the same time. Any number of writers can be added.
+    ///
+    /// This must be called before the output is set, and before any frames are
+    /// added, otherwise GifskiInvalidState is raised.
+    ///
+    /// If the output or any writer fails, the encoding stops, and the error is
+    /// raised from finish(). The writers after the failed one don't get the
+    /// chunk being written, so all of them end up with an incomplete gif.
+    /// A file set with set_file_output() is removed then.
+    ///
+    /// Example:
+    ///     hasher = HashWriter()
+    ///     g.add_write_callback(hasher)
+    ///     g.set_file_output("output/path.gif")
+    ///
+    /// Parameters
+    /// ----------
+    /// writer : file-like object
+    ///     Object with a write() method accepting bytes, such as io.BytesIO.
+    #[pyo3(text_signature = "(self, writer, /)")]
+    fn add_write_callback(&mut self, py: Python<'_>, writer: PyObject) -> PyResult<()> {
+        if !writer.as_ref(py).hasattr("write")? {
+            return Err(exceptions::PyTypeError::new_err("writer must have a write() method"));
+        }
+        self.check_not_finished()?;
+        if self.has_output || self.callbacks.output.lock().unwrap().is_some() {
+            return Err(error_from_code(GifskiError::INVALID_STATE));
+        }
+        self.callbacks.tee_writers.lock().unwrap().push(writer);
+        Ok(())
+    }
+
+    /// Set the gif output destination to a file descriptor, e.g. 1 for stdout.
+    ///
+    /// The gif is written directly, without holding the GIL. The file descriptor
+    /// stays owned by the caller: it's not closed by gifski, and it must remain
+    /// open until finish() returns.
+    ///
+    /// This method should only be called once on a Gifski object, and only one
+    /// output can be set. It's available only on Unix-like systems. Like
+    /// set_file_output(), it can be called before or after adding frames.
+    ///
+    /// Example for writing to stdout:
+    ///     g.set_fd_output(sys.stdout.fileno())
+    ///
+    /// Parameters
+    /// ----------
+    /// fd : int
+    ///     File descriptor opened for writing.
+    #[cfg(unix)]
+    #[pyo3(text_signature = "(self, fd, /)")]
+    unsafe fn set_fd_output(&mut self, py: Python<'_>, fd: i32) -> PyResult<()> {
+        use std::os::unix::io::FromRawFd;
+
+        self.check_not_finished()?;
+        // raises OSError if the fd isn't open
+        let fcntl = py.import("fcntl")?;
+        let flags: i32 = fcntl.call_method1("fcntl", (fd, fcntl.getattr("F_GETFL")?))?.extract()?;
+        let os = py.import("os")?;
+        let access_mode = flags & (os.getattr("O_WRONLY")?.extract::<i32>()? | os.getattr("O_RDWR")?.extract::<i32>()?);
+        if access_mode == 0 {
+            return Err(exceptions::PyValueError::new_err(format!("file descriptor {} isn't open for writing", fd)));
+        }
+        if !self.can_set_output() {
+            return Err(error_from_code(GifskiError::INVALID_STATE));
+        }
+
+        let file = mem::ManuallyDrop::new(std::fs::File::from_raw_fd(fd));
+        let success = self.start_output(Output::Fd(file));
+        if success == GifskiError::OK {
+            self.has_output = true;
+            return Ok(());
+        }
+
+        Err(self.callbacks.error(success))
+    }
+
+    /// Specify a new gif frame using a pixel buffer.
+    ///
+    /// Example for getting a pixel buffer:
+    ///     from PIL import Image
+    ///     image = Image.open(image_path, mode='r')
+    ///     pixels = image.convert('RGBA').tobytes()
+    ///
+    /// This blocks while earlier frames are being encoded. The GIL is released
+    /// in the meantime (in all add_frame_* methods), so the next frames can be
+    /// prepared on other Python threads.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes-like
+    ///     RGBA pixel data, 4 bytes per pixel. The number of pixels must match the
+    ///     width and height provided when creating the Gifski object.
+    ///     Any contiguous buffer, such as bytes, bytearray or memoryview, is read
+    ///     without copying. It must not be modified until this method returns.
+    /// timestamp : float
+    ///     Presentation time of the frame in seconds. It must be greater than the
+    ///     previous frame's timestamp (in all add_frame_* methods).
+    ///
+    /// Returns
+    /// -------
+    /// int
+    ///     Index of the added frame, starting from 0. It's the frame_count from
+    ///     before the call.
+    #[pyo3(text_signature = "(self, pixels, timestamp, /)")]
+    unsafe fn add_frame_rgba(&mut self, py: Python<'_>, pixels: &PyAny, timestamp: f64) -> PyResult<u32> {
+        let index = self.frame_count;
+        let buffer = pixel_buffer(pixels)?;
+        self.add_frame_rgba_slice(py, buffer_as_slice(&buffer), timestamp)?;
+        Ok(index)
+    }
+
+    /// Specify a new gif frame using a pixel buffer, optionally without a timestamp.
+    ///
+    /// Without a timestamp, it's computed from the number of frames added so far
+    /// and the fps given when creating the Gifski object.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes
+    ///     RGBA pixel data, same as for add_frame_rgba().
+    /// timestamp : float, optional
+    ///     Presentation time of the frame in seconds.
+    ///
+    /// Returns the index of the added frame, like add_frame_rgba().
+    #[args(timestamp="None")]
+    #[pyo3(text_signature = "(self, pixels, /, timestamp=None)")]
+    unsafe fn add_frame(&mut self, py: Python<'_>, pixels: &PyAny, timestamp: Option<f64>) -> PyResult<u32> {
+        let timestamp = match (timestamp, self.fps) {
+            (Some(timestamp), _) => timestamp,
+            (None, Some(fps)) => self.frame_count as f64 / fps,
+            (None, None) => return Err(exceptions::PyValueError::new_err("timestamp is required when fps isn't set")),
+        };
+        self.add_frame_rgba(py, pixels, timestamp)
+    }
+
+    /// Specify a new gif frame using a pixel buffer, and how long it's going to be displayed.
+    ///
+    /// Timestamps are computed by adding up the durations of the previous frames,
+    /// so this shouldn't be mixed with methods taking a timestamp.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes
+    ///     RGBA pixel data, same as for add_frame_rgba().
+    /// duration : float
+    ///     Positive number of seconds.
+    #[pyo3(text_signature = "(self, pixels, duration, /)")]
+    unsafe fn add_frame_rgba_duration(&mut self, py: Python<'_>, pixels: &PyAny, duration: f64) -> PyResult<()> {
+        if !(duration > 0.0 && duration.is_finite()) {
+            return Err(exceptions::PyValueError::new_err("duration must be greater than 0"));
+        }
+        self.add_frame_rgba(py, pixels, self.elapsed)?;
+        self.elapsed += duration;
+        Ok(())
+    }
+
+    /// Specify a new gif frame using a pixel buffer, with the timestamp in milliseconds.
+    ///
+    /// The timestamps are compared as integers, so they don't accumulate
+    /// floating-point errors, e.g. when they're computed by adding up 1000/fps.
+    /// They're converted to seconds for the encoder, which rounds the frame
+    /// delays to hundredths of a second.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes-like
+    ///     RGBA pixel data, same as for add_frame_rgba().
+    /// ms : int
+    ///     Presentation time of the frame in milliseconds. It must be greater than
+    ///     the previous frame's.
+    ///
+    /// Returns the index of the added frame, like add_frame_rgba().
+    #[pyo3(text_signature = "(self, pixels, ms, /)")]
+    unsafe fn add_frame_rgba_ms(&mut self, py: Python<'_>, pixels: &PyAny, ms: u64) -> PyResult<u32> {
+        validate_timestamp_ms(ms, self.last_timestamp_ms, self.frame_count).map_err(exceptions::PyValueError::new_err)?;
+        let index = self.add_frame_rgba(py, pixels, ms as f64 / 1000.)?;
+        self.last_timestamp_ms = Some(ms);
+        Ok(index)
+    }
+
+    /// Specify several gif frames at once, from an iterable of (pixels, timestamp) tuples.
+    ///
+    /// Each frame is added like with add_frame_rgba(). If a frame is invalid,
+    /// the frames before it stay added, and the exception is raised with
+    /// a frame_index attribute saying which frame of the iterable has failed.
+    ///
+    /// Example:
+    ///     g.add_frames((img.convert('RGBA').tobytes(), i / 24) for i, img in enumerate(imgs))
+    ///
+    /// Parameters
+    /// ----------
+    /// frames : iterable
+    ///     Tuples of RGBA pixel data (bytes) and timestamps (float).
+    #[pyo3(text_signature = "(self, frames, /)")]
+    unsafe fn add_frames(&mut self, py: Python<'_>, frames: &PyAny) -> PyResult<()> {
+        for (index, frame) in frames.iter()?.enumerate() {
+            let res = frame.and_then(|frame| {
+                let (pixels, timestamp): (&PyAny, f64) = frame.extract()?;
+                self.add_frame_rgba(py, pixels, timestamp)
+            });
+            if let Err(err) = res {
+                return Err(frame_error(py, err, index, None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Specify a new gif frame at the given index, so that frames can be added in any order.
+    ///
+    /// The Gifski object isn't locked while the frame is being added, so this
+    /// method can be called concurrently from several Python threads, e.g. by a
+    /// thread pool. Each index can be added only once, and the timestamps must
+    /// increase with the indices. finish() raises ValueError if any index from 0
+    /// to frame_count-1 is missing.
+    ///
+    /// This can't be mixed with other add_frame_* methods, and can't be used
+    /// with dedupe or keep_frames, which need the frames in order.
+    ///
+    /// Parameters
+    /// ----------
+    /// index : int
+    ///     Position of the frame in the gif, starting from 0.
+    /// pixels : bytes-like
+    ///     RGBA pixel data, same as for add_frame_rgba().
+    /// timestamp : float
+    ///     Presentation time of the frame in seconds.
+    #[pyo3(text_signature = "(self, index, pixels, timestamp, /)")]
+    unsafe fn add_frame_rgba_at(&self, py: Python<'_>, index: u32, pixels: &PyAny, timestamp: f64) -> PyResult<()> {
+        let (handle, width, height, speed) = (self._handle, self.width, self.height, self.speed);
+        let buffer = pixel_buffer(pixels)?;
+        let pixels = buffer_as_slice(&buffer);
+        check_not_empty(pixels)?;
+        if pixels.len() % 4 != 0 {
+            return Err(exceptions::PyValueError::new_err("pixels must be in RGBA format, 4 bytes per pixel"));
+        }
+        if frame_len(self.width, self.height, 4) != Some(pixels.len()) {
+            return Err(exceptions::PyValueError::new_err("pixel width*height doesn't match the width*height used during construction"));
+        }
+        self.check_not_finished()?;
+        if self.frame_count > 0 {
+            return Err(exceptions::PyRuntimeError::new_err("add_frame_rgba_at() can't be mixed with other add_frame_* methods"));
+        }
+        if self.dedupe.is_some() || self.kept_frames.is_some() {
+            return Err(exceptions::PyRuntimeError::new_err("add_frame_rgba_at() can't be used with dedupe or keep_frames"));
+        }
+        {
+            let mut indexed_frames = self.indexed_frames.lock().unwrap();
+            check_frame_limit(indexed_frames.len() as u32, self.max_frames)
+                .and_then(|_| check_indexed_frame(&indexed_frames, index, timestamp))
+                .map_err(exceptions::PyValueError::new_err)?;
+            // while it's locked, so that concurrent calls don't set the output twice
+            self.ensure_output()?;
+            // reserved before adding, so that concurrent calls can't use the same index
+            indexed_frames.insert(index, timestamp);
+        }
+
+        let pixels = cast_pixels::<RGBA8>(pixels);
+        let alpha_threshold = self.alpha_threshold;
+        let success = py.allow_threads(|| {
+            let pixels = thresholded_alpha(pixels, alpha_threshold);
+            gifski_add_frame_rgba(handle as *const GifskiHandle, index, width, height, pixels.as_ptr(), timestamp / speed)
+        });
+        if success != GifskiError::OK {
+            self.indexed_frames.lock().unwrap().remove(&index);
+            return Err(self.callbacks.error(success));
+        }
+        Ok(())
+    }
+
+    /// Specify a new gif frame using a pixel buffer with padding after each row.
+    ///
+    /// This allows adding a part of a larger framebuffer without copying it:
+    /// pixels can start at the top left corner of the part, with bytes_per_row
+    /// of the whole framebuffer. The last row doesn't need to be padded.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes
+    ///     RGBA pixel data, 4 bytes per pixel, at least
+    ///     bytes_per_row*(height-1) + width*4 bytes long.
+    /// bytes_per_row : int
+    ///     Distance between starts of rows. Must be a multiple of 4, and at least width*4.
+    #[pyo3(text_signature = "(self, pixels, timestamp, bytes_per_row, /)")]
+    unsafe fn add_frame_rgba_stride(&mut self, py: Python<'_>, pixels: &[u8], timestamp: f64, bytes_per_row: u32) -> PyResult<()> {
+        let (handle, frame_number, width, height, speed) = (self._handle, self.frames_sent, self.width, self.height, self.speed);
+        check_not_empty(pixels)?;
+        if bytes_per_row % 4 != 0 || bytes_per_row < width * 4 {
+            return Err(exceptions::PyValueError::new_err("bytes_per_row must be a multiple of 4, and at least width*4"));
+        }
+        let required_len = (bytes_per_row as u64 * (height - 1) as u64 + width as u64 * 4) as usize;
+        if pixels.len() < required_len {
+            return Err(exceptions::PyValueError::new_err("pixels are too short for the given bytes_per_row and the height used during construction"));
+        }
+        let timestamp = self.check_new_frame(timestamp)?;
+
+        self.ensure_output()?;
+        let pixels = cast_pixels::<RGBA8>(&pixels[..required_len]);
+        let copies = FrameCopies { previous: self.dedupe.as_mut(), kept: self.kept_frames.as_mut(), alpha_threshold: self.alpha_threshold };
+        let res = py.allow_threads(|| match copies {
+            FrameCopies { previous: None, kept: None, alpha_threshold: None } => frame_result(gifski_add_frame_rgba_stride(handle as *const GifskiHandle, frame_number, width, height, bytes_per_row, pixels.as_ptr(), timestamp / speed)),
+            copies => {
+                let rgba: Vec<_> = pixels.chunks(bytes_per_row as usize / 4).flat_map(|row| &row[..width as usize]).copied().collect();
+                add_rgba_frame(handle, frame_number, width, height, &rgba, timestamp / speed, copies)
+            },
+        });
+        self.frame_added_or_merged(res, timestamp)
+    }
+
+    /// Specify a new gif frame using a NumPy array.
+    ///
+    /// The array is read without copying, so it must be C-contiguous. Arrays
+    /// that aren't (e.g. transposed or sliced views) can be made contiguous with
+    /// np.ascontiguousarray(array). Other objects supporting the buffer protocol
+    /// with the same shape and item type are accepted too.
+    ///
+    /// Parameters
+    /// ----------
+    /// array : numpy.ndarray
+    ///     RGBA pixels, with shape (height, width, 4) and dtype uint8.
+    #[pyo3(text_signature = "(self, array, timestamp, /)")]
+    unsafe fn add_frame_ndarray(&mut self, py: Python<'_>, array: &PyAny, timestamp: f64) -> PyResult<()> {
+        let buffer = PyBuffer::<u8>::get(array)
+            .map_err(|_| exceptions::PyTypeError::new_err("array must support the buffer protocol, and have dtype uint8"))?;
+        let expected_shape = [self.height as usize, self.width as usize, 4];
+        if self.fit != Fit::Error && buffer.shape().len() == 3 && buffer.shape()[2] == 4 && buffer.shape() != &expected_shape[..] {
+            check_c_contiguous(&buffer, "array")?;
+            let (src_width, src_height) = (buffer.shape()[1] as u32, buffer.shape()[0] as u32);
+            return self.add_frame_rgba_resized(py, buffer_as_slice(&buffer), src_width, src_height, timestamp);
+        }
+        if buffer.shape() != &expected_shape[..] {
+            return Err(exceptions::PyValueError::new_err(format!("array must have shape (height, width, 4) = {:?}, not {:?}", expected_shape, buffer.shape())));
+        }
+        check_c_contiguous(&buffer, "array")?;
+
+        self.add_frame_rgba_slice(py, buffer_as_slice(&buffer), timestamp)
+    }
+
+    /// Specify all frames of an animation at once, using a NumPy array.
+    ///
+    /// The frames are read from the array without copying, so like in
+    /// add_frame_ndarray() it must be C-contiguous and have dtype uint8.
+    /// The timestamps are computed from fps, continuing from the frames added
+    /// before, like with add_frame(). If a frame can't be added, the frames
+    /// before it stay added, and the exception is raised with a frame_index
+    /// attribute saying which frame has failed.
+    ///
+    /// Example:
+    ///     g.add_all_ndarray(video, fps=30)  # video.shape == (frames, height, width, 4)
+    ///
+    /// Parameters
+    /// ----------
+    /// array : numpy.ndarray
+    ///     RGBA pixels, with shape (frames, height, width, 4) and dtype uint8.
+    /// fps : float
+    ///     Frames per second.
+    #[pyo3(text_signature = "(self, array, fps, /)")]
+    unsafe fn add_all_ndarray(&mut self, py: Python<'_>, array: &PyAny, fps: f64) -> PyResult<()> {
+        let fps = fps_arg(fps)?;
+        let buffer = PyBuffer::<u8>::get(array)
+            .map_err(|_| exceptions::PyTypeError::new_err("array must support the buffer protocol, and have dtype uint8"))?;
+        let frame_shape = [self.height as usize, self.width as usize, 4];
+        if buffer.shape().len() != 4 || buffer.shape()[1..] != frame_shape[..] {
+            return Err(exceptions::PyValueError::new_err(format!("array must have shape (frames, height, width, 4) = (n, {}, {}, 4), not {:?}", self.height, self.width, buffer.shape())));
+        }
+        check_c_contiguous(&buffer, "array")?;
+
+        let first_frame = self.frame_count;
+        let frame_len = frame_shape.iter().product();
+        for (index, pixels) in buffer_as_slice(&buffer).chunks_exact(frame_len).enumerate() {
+            let timestamp = (first_frame as usize + index) as f64 / fps;
+            if let Err(err) = self.add_frame_rgba_slice(py, pixels, timestamp) {
+                return Err(frame_error(py, err, index, None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Specify a new gif frame using a PIL (Pillow) image.
+    ///
+    /// Images in RGB, RGBA or L (grayscale) mode are used directly, other modes are converted to RGBA.
+    ///
+    /// Example:
+    ///     from PIL import Image
+    ///     g.add_frame_pil(Image.open(image_path), timestamp)
+    ///
+    /// Parameters
+    /// ----------
+    /// image : PIL.Image.Image
+    ///     Image with the size (width, height) provided when creating the Gifski object.
+    #[pyo3(text_signature = "(self, image, timestamp, /)")]
+    unsafe fn add_frame_pil(&mut self, py: Python<'_>, image: &PyAny, timestamp: f64) -> PyResult<()> {
+        let size: (u32, u32) = image.getattr("size")?.extract()?;
+        if self.fit != Fit::Error && size != (self.width, self.height) {
+            let pixels = image.call_method1("convert", ("RGBA",))?.call_method0("tobytes")?.downcast::<PyBytes>()?;
+            return self.add_frame_rgba_resized(py, pixels.as_bytes(), size.0, size.1, timestamp);
+        }
+        if size != (self.width, self.height) {
+            return Err(exceptions::PyValueError::new_err(format!("image size {:?} doesn't match the size ({}, {}) used during construction", size, self.width, self.height)));
+        }
+        let mode: String = image.getattr("mode")?.extract()?;
+        if mode == "RGB" {
+            let pixels = image.call_method0("tobytes")?.downcast::<PyBytes>()?;
+            return self.add_frame_rgb(py, pixels.as_bytes(), timestamp);
+        }
+        if mode == "L" {
+            let pixels = image.call_method0("tobytes")?.downcast::<PyBytes>()?;
+            return self.add_frame_gray(py, pixels.as_bytes(), timestamp);
+        }
+
+        let image = if mode == "RGBA" { image } else {
+            image.call_method1("convert", ("RGBA",))
+                .map_err(|err| exceptions::PyValueError::new_err(format!("can't convert image mode {} to RGBA: {}", mode, err)))?
+        };
+        let pixels = image.call_method0("tobytes")?.downcast::<PyBytes>()?;
+        self.add_frame_rgba_slice(py, pixels.as_bytes(), timestamp)
+    }
+
+    /// Specify a new gif frame using a pixel buffer with the alpha byte first.
+    ///
+    /// The bytes of every pixel must be in A, R, G, B order. Colors must not be
+    /// premultiplied by alpha.
+    ///
+    /// Note that Cairo's FORMAT_ARGB32 stores pixels as native-endian 32-bit
+    /// integers, so on little-endian machines its bytes are actually in B, G, R, A
+    /// order, and the colors are premultiplied.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes
+    ///     ARGB pixel data, 4 bytes per pixel. The number of pixels must match the
+    ///     width and height provided when creating the Gifski object.
+    #[pyo3(text_signature = "(self, pixels, timestamp, /)")]
+    unsafe fn add_frame_argb(&mut self, py: Python<'_>, pixels: &[u8], timestamp: f64) -> PyResult<()> {
+        self.add_frame_converted(py, pixels, timestamp, "ARGB", 4,
+            |pixels| cast_pixels::<ARGB8>(pixels).iter().map(|p| RGBA8::new(p.r, p.g, p.b, p.a)).collect(),
+            Some(|handle, frame_number, width, height, pixels, timestamp| {
+                gifski_add_frame_argb(handle as *const GifskiHandle, frame_number, width, width * 4, height, cast_pixels::<ARGB8>(pixels).as_ptr(), timestamp)
+            }))
+    }
+
+    /// Specify a new gif frame using a pixel buffer in B, G, R, A order.
+    ///
+    /// This is the usual order of screenshots from Windows GDI and DirectX.
+    /// The channels are swapped without holding the GIL. Colors must not be
+    /// premultiplied by alpha.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes
+    ///     BGRA pixel data, 4 bytes per pixel. The number of pixels must match the
+    ///     width and height provided when creating the Gifski object.
+    #[pyo3(text_signature = "(self, pixels, timestamp, /)")]
+    unsafe fn add_frame_bgra(&mut self, py: Python<'_>, pixels: &[u8], timestamp: f64) -> PyResult<()> {
+        self.add_frame_converted(py, pixels, timestamp, "BGRA", 4, |pixels| bgra_to_rgba(cast_pixels(pixels)), None)
+    }
+
+    /// Specify a new gif frame using a pixel buffer without an alpha channel.
+    ///
+    /// Example for getting a pixel buffer:
+    ///     from PIL import Image
+    ///     image = Image.open(image_path, mode='r')
+    ///     pixels = image.convert('RGB').tobytes()
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes
+    ///     RGB pixel data, 3 bytes per pixel. The number of pixels must match the
+    ///     width and height provided when creating the Gifski object.
+    #[pyo3(text_signature = "(self, pixels, timestamp, /)")]
+    unsafe fn add_frame_rgb(&mut self, py: Python<'_>, pixels: &[u8], timestamp: f64) -> PyResult<()> {
+        self.add_frame_converted(py, pixels, timestamp, "RGB", 3,
+            |pixels| cast_pixels::<RGB8>(pixels).iter().map(|p| p.alpha(255)).collect(),
+            Some(|handle, frame_number, width, height, pixels, timestamp| {
+                gifski_add_frame_rgb(handle as *const GifskiHandle, frame_number, width, width * 3, height, cast_pixels::<RGB8>(pixels).as_ptr(), timestamp)
+            }))
+    }
+
+    /// Specify a new gif frame using grayscale pixels.
+    ///
+    /// The pixels are converted to RGBA without holding the GIL.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes
+    ///     Grayscale pixel data, 1 byte per pixel. The number of pixels must match the
+    ///     width and height provided when creating the Gifski object.
+    #[pyo3(text_signature = "(self, pixels, timestamp, /)")]
+    unsafe fn add_frame_gray(&mut self, py: Python<'_>, pixels: &[u8], timestamp: f64) -> PyResult<()> {
+        self.add_frame_converted(py, pixels, timestamp, "grayscale", 1, |pixels| pixels.iter().map(|&y| RGBA8::new(y, y, y, 255)).collect(), None)
+    }
+
+    /// Specify a new gif frame by updating a rectangle of the previous frame.
+    ///
+    /// The w*h rectangle at (x, y) is copied from pixels to the same position
+    /// in the previous frame added with this method, and the result is added as
+    /// a new frame. Pixels outside of the rectangle stay the same. Before the
+    /// first call the frame is fully transparent.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes
+    ///     RGBA pixel data, 4 bytes per pixel, in rows of full_width pixels. It has
+    ///     to contain the rectangle, but rows below it can be left out.
+    /// x, y : int
+    ///     Position of the rectangle's top left corner.
+    /// w, h : int
+    ///     Size of the rectangle, which must fit within the width and height
+    ///     provided when creating the Gifski object.
+    /// full_width : int
+    ///     Number of pixels in each row of pixels.
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(text_signature = "(self, pixels, timestamp, x, y, w, h, full_width, /)")]
+    unsafe fn add_frame_rgba_region(&mut self, py: Python<'_>, pixels: &[u8], timestamp: f64, x: u32, y: u32, w: u32, h: u32, full_width: u32) -> PyResult<()> {
+        let (handle, frame_number, width, height, speed) = (self._handle, self.frames_sent, self.width, self.height, self.speed);
+        check_not_empty(pixels)?;
+        let region = Region { x, y, w, h, full_width };
+        let required_len = region.required_len(width, height).map_err(exceptions::PyValueError::new_err)?;
+        if pixels.len() % 4 != 0 {
+            return Err(exceptions::PyValueError::new_err("pixels must be in RGBA format, 4 bytes per pixel"));
+        }
+        if pixels.len() < required_len * 4 {
+            return Err(exceptions::PyValueError::new_err(format!("pixels must have at least {} pixels to contain the region", required_len)));
+        }
+        let timestamp = self.check_new_frame(timestamp)?;
+
+        self.ensure_output()?;
+        if self.canvas.is_empty() {
+            self.canvas = vec![RGBA8::new(0, 0, 0, 0); width as usize * height as usize];
+        }
+        let canvas = &mut self.canvas;
+        let copies = FrameCopies { previous: self.dedupe.as_mut(), kept: self.kept_frames.as_mut(), alpha_threshold: self.alpha_threshold };
+        let pixels = cast_pixels::<RGBA8>(pixels);
+        let res = py.allow_threads(|| {
+            region.copy(pixels, canvas, width as usize);
+            add_rgba_frame(handle, frame_number, width, height, canvas, timestamp / speed, copies)
+        });
+        self.frame_added_or_merged(res, timestamp)
+    }
+
+    /// Specify a new gif frame using palette indices.
+    ///
+    /// The indices are converted to RGBA without holding the GIL. The frame is
+    /// still quantized by gifski, so the palette doesn't have to be used as-is
+    /// (see set_palette() for that).
+    ///
+    /// Parameters
+    /// ----------
+    /// indices : bytes
+    ///     1 byte per pixel, an index into palette. The number of pixels must
+    ///     match the width and height provided when creating the Gifski object.
+    /// palette : list of (int, int, int, int) tuples
+    ///     Up to 256 colors as (r, g, b, a) tuples with values from 0 to 255.
+    #[pyo3(text_signature = "(self, indices, palette, timestamp, /)")]
+    unsafe fn add_frame_indexed(&mut self, py: Python<'_>, indices: &[u8], palette: Vec<(u8, u8, u8, u8)>, timestamp: f64) -> PyResult<()> {
+        let (handle, frame_number, width, height, speed) = (self._handle, self.frames_sent, self.width, self.height, self.speed);
+        check_not_empty(indices)?;
+        if palette.is_empty() || palette.len() > 256 {
+            return Err(exceptions::PyValueError::new_err("the palette must have between 1 and 256 colors"));
+        }
+        if frame_len(self.width, self.height, 1) != Some(indices.len()) {
+            return Err(exceptions::PyValueError::new_err("pixel width*height doesn't match the width*height used during construction"));
+        }
+        let timestamp = self.check_new_frame(timestamp)?;
+
+        let palette: Vec<_> = palette.into_iter().map(|(r, g, b, a)| RGBA8::new(r, g, b, a)).collect();
+        let rgba = py.allow_threads(|| expand_indexed(indices, &palette))
+            .map_err(|i| exceptions::PyValueError::new_err(format!("index {} of pixel {} is out of range of the palette with {} colors", indices[i], i, palette.len())))?;
+
+        self.ensure_output()?;
+        let copies = FrameCopies { previous: self.dedupe.as_mut(), kept: self.kept_frames.as_mut(), alpha_threshold: self.alpha_threshold };
+        let res = py.allow_threads(|| {
+            add_rgba_frame(handle, frame_number, width, height, &rgba, timestamp / speed, copies)
+        });
+        self.frame_added_or_merged(res, timestamp)
+    }
+
+    /// Specify a new gif frame using RGBA pixels with 16 bits per channel.
+    ///
+    /// The samples are rounded to 8 bits without holding the GIL.
+    ///
+    /// Parameters
+    /// ----------
+    /// pixels : bytes-like or uint16 array
+    ///     RGBA pixel data, 4 native-endian 16-bit samples per pixel, e.g. a
+    ///     contiguous numpy array of dtype uint16. Its size in bytes must be
+    ///     width*height*8, with the width and height provided when creating the
+    ///     Gifski object.
+    #[pyo3(text_signature = "(self, pixels, timestamp, /)")]
+    unsafe fn add_frame_rgba16(&mut self, py: Python<'_>, pixels: &PyAny, timestamp: f64) -> PyResult<()> {
+        let (handle, frame_number, width, height, speed) = (self._handle, self.frames_sent, self.width, self.height, self.speed);
+        // kept alive while the slice is used
+        let buffer16;
+        let buffer8;
+        let pixels = match PyBuffer::<u16>::get(pixels) {
+            Ok(buffer) => {
+                check_c_contiguous(&buffer, "pixels")?;
+                buffer16 = buffer;
+                buffer_as_slice(&buffer16)
+            },
+            Err(_) => {
+                buffer8 = pixel_buffer(pixels)?;
+                buffer_as_slice(&buffer8)
+            },
+        };
+        check_not_empty(pixels)?;
+        if frame_len(self.width, self.height, 8) != Some(pixels.len()) {
+            return Err(exceptions::PyValueError::new_err("pixels must be width*height*8 bytes, 4 16-bit samples per pixel"));
+        }
+        let timestamp = self.check_new_frame(timestamp)?;
+
+        self.ensure_output()?;
+        let copies = FrameCopies { previous: self.dedupe.as_mut(), kept: self.kept_frames.as_mut(), alpha_threshold: self.alpha_threshold };
+        let res = py.allow_threads(|| {
+            let rgba = rgba16_to_rgba8(pixels);
+            add_rgba_frame(handle, frame_number, width, height, &rgba, timestamp / speed, copies)
+        });
+        self.frame_added_or_merged(res, timestamp)
+    }
+
+    /// Specify a new gif frame by reading a PNG file.
+    ///
+    /// The file is decoded by gifski without holding the GIL, which is faster
+    /// than decoding it in Python.
+    /// The image must have the width and height provided when creating the Gifski object.
+    ///
+    /// Parameters
+    /// ----------
+    /// path : str or os.PathLike
+    ///     Path to a PNG file.
+    #[cfg(feature = "png")]
+    #[pyo3(text_signature = "(self, path, timestamp, /)")]
+    unsafe fn add_frame_png_file(&mut self, py: Python<'_>, path: PathBuf, timestamp: f64) -> PyResult<()> {
+        let (handle, frame_number, speed) = (self._handle, self.frames_sent, self.speed);
+        if !path.is_file() {
+            return Err(exceptions::PyFileNotFoundError::new_err(format!("{} not found", path.display())));
+        }
+        let c_path = path_to_cstring(&path).map_err(exceptions::PyValueError::new_err)?;
+        // the pixels are needed for keeping a copy, or changing their alpha
+        if self.kept_frames.is_some() || self.alpha_threshold.is_some() {
+            // checked before reading the file, and again with the decoded pixels
+            self.check_new_frame(timestamp)?;
+            let data = std::fs::read(&path)?;
+            return self.add_frame_png_bytes(py, &data, timestamp);
+        }
+        let timestamp = self.check_new_frame(timestamp)?;
+
+        self.ensure_output()?;
+        // the file is decoded later, so it can't be compared
+        if let Some(previous) = &mut self.dedupe {
+            *previous = PreviousFrame::default();
+        }
+        let success = py.allow_threads(|| {
+            gifski_add_frame_png_file(handle as *const GifskiHandle, frame_number, c_path.as_ptr(), timestamp / speed)
+        });
+        if success != GifskiError::OK {
+            return Err(self.callbacks.error(success));
+        }
+
+        self.frame_added(timestamp);
+        return Ok(());
+    }
+
+    /// Specify a new gif frame by decoding PNG data from memory.
+    ///
+    /// Works like add_frame_png_file(), without writing the data to a file.
+    /// The PNG is decoded without holding the GIL.
+    /// The image must have the width and height provided when creating the Gifski object.
+    ///
+    /// Parameters
+    /// ----------
+    /// data : bytes
+    ///     Contents of a PNG file.
+    #[cfg(feature = "png")]
+    #[pyo3(text_signature = "(self, data, timestamp, /)")]
+    unsafe fn add_frame_png_bytes(&mut self, py: Python<'_>, data: &[u8], timestamp: f64) -> PyResult<()> {
+        let (handle, frame_number, width, height, speed) = (self._handle, self.frames_sent, self.width, self.height, self.speed);
+        let timestamp = self.check_new_frame(timestamp)?;
+
+        let image = py.allow_threads(|| lodepng::decode32(data))
+            .map_err(|err| exceptions::PyValueError::new_err(format!("can't decode the PNG: {}", err)))?;
+        if (image.width, image.height) != (width as usize, height as usize) {
+            return Err(exceptions::PyValueError::new_err(format!("the PNG has size {}x{}, but the gif is {}x{}", image.width, image.height, width, height)));
+        }
+
+        self.ensure_output()?;
+        let copies = FrameCopies { previous: self.dedupe.as_mut(), kept: self.kept_frames.as_mut(), alpha_threshold: self.alpha_threshold };
+        let res = py.allow_threads(|| {
+            add_rgba_frame(handle, frame_number, width, height, &image.buffer, timestamp / speed, copies)
+        });
+        self.frame_added_or_merged(res, timestamp)
+    }
+
+    /// Specify frames decoded from a GIF file, e.g. to join several clips.
+    ///
+    /// The frames are composited the way browsers display them, and added at
+    /// timestamps starting from timestamp_offset, following the GIF's delays.
+    /// Delays of 0 or 1 hundredths of a second are treated as 10, like in
+    /// browsers. The GIF is decoded without holding the GIL, and it must have
+    /// the width and height provided when creating the Gifski object. If a frame
+    /// is invalid, the frames before it stay added.
+    ///
+    /// Example:
+    ///     end = g.add_frames_from_gif("intro.gif")
+    ///     g.add_frames_from_gif("main.gif", timestamp_offset=end)
+    ///
+    /// Parameters
+    /// ----------
+    /// path : str or os.PathLike
+    ///     GIF file to read.
+    /// timestamp_offset : float
+    ///     Timestamp of the first frame of the GIF.
+    ///
+    /// Returns
+    /// -------
+    /// float
+    ///     The time when the last frame ends, which is the timestamp_offset
+    ///     for the next clip.
+    #[args(timestamp_offset = "0.0")]
+    #[pyo3(text_signature = "(self, path, /, timestamp_offset=0.0)")]
+    unsafe fn add_frames_from_gif(&mut self, py: Python<'_>, path: PathBuf, timestamp_offset: f64) -> PyResult<f64> {
+        use rgb::ComponentBytes;
+
+        self.check_not_finished()?;
+        let file = std::fs::File::open(&path)?;
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = py.allow_threads(|| options.read_info(file))
+            .map_err(|err| exceptions::PyValueError::new_err(format!("can't decode {}: {}", path.display(), err)))?;
+        let gif_size = (u32::from(decoder.width()), u32::from(decoder.height()));
+        if gif_size != (self.width, self.height) {
+            return Err(exceptions::PyValueError::new_err(format!("{} has size {}x{}, but the gif is {}x{}", path.display(), gif_size.0, gif_size.1, self.width, self.height)));
+        }
+
+        let mut screen = gif_dispose::Screen::new_decoder(&decoder);
+        let mut timestamp = timestamp_offset;
+        let mut index = 0;
+        loop {
+            let frame = py.allow_threads(|| -> Result<_, String> {
+                let frame = match decoder.read_next_frame().map_err(|err| err.to_string())? {
+                    Some(frame) => frame,
+                    None => return Ok(None),
+                };
+                screen.blit_frame(frame).map_err(|err| err.to_string())?;
+                Ok(Some((screen.pixels.pixels().collect::<Vec<RGBA8>>(), gif_delay(frame.delay))))
+            }).map_err(|err| exceptions::PyValueError::new_err(format!("can't decode frame {} of {}: {}", index, path.display(), err)))?;
+            let (pixels, delay) = match frame {
+                Some(frame) => frame,
+                None => break,
+            };
+            if let Err(err) = self.add_frame_rgba_slice(py, pixels.as_bytes(), timestamp) {
+                return Err(frame_error(py, err, index, Some(&path)));
+            }
+            timestamp += delay;
+            index += 1;
+        }
+        if index == 0 {
+            return Err(exceptions::PyValueError::new_err(format!("{} has no frames", path.display())));
+        }
+        Ok(timestamp)
+    }
+
+    /// Make a gif file from a list of PNG files, in one call.
+    ///
+    /// The size of the gif is taken from the first PNG file, and all other files
+    /// must have the same size. Returns the same FinishStats as finish().
+    ///
+    /// Example:
+    ///     Gifski.from_png_files(sorted(glob.glob("frames/*.png")), 24, "output/path.gif")
+    ///
+    /// Parameters
+    /// ----------
+    /// paths : list of str or os.PathLike
+    ///     Paths to PNG files, in the order of frames.
+    /// fps : float
+    ///     Frames per second.
+    /// output : str or os.PathLike
+    ///     File path for writing the output gif.
+    /// quality : int
+    ///     integer from 1 (best compression) to 100 (best quality)
+    #[cfg(feature = "png")]
+    #[classmethod]
+    #[args(quality=90)]
+    #[pyo3(text_signature = "(paths, fps, output, /, quality=90)")]
+    unsafe fn from_png_files(_cls: &pyo3::types::PyType, py: Python<'_>, paths: Vec<PathBuf>, fps: f64, output: PathBuf, quality: i64) -> PyResult<PyFinishStats> {
+        Self::encode_png_files(py, paths, fps, output, quality)
+    }
+
+    /// Finalize the gif and write the output.
+    ///
+    /// This waits until all frames have been encoded and written, which may take a while.
+    /// The GIL is released in the meantime, so other Python threads can run.
+    /// Ctrl-C aborts the encoding, and raises KeyboardInterrupt.
+    ///
+    /// Returns a FinishStats object with frames_written, output_bytes (None if
+    /// unknown), the duration of encoding in seconds, and complete, which is
+    /// True. Errors writing the output are raised instead.
+    ///
+    /// With a timeout, the encoding is aborted and TimeoutError is raised if it
+    /// doesn't finish in time. Aborting waits for the frame being processed, so
+    /// it may take a bit longer than the timeout.
+    ///
+    /// RuntimeError is raised if no output has been set, and then the encoder
+    /// isn't finished. Use finish_to_bytes() to get the gif without an output.
+    ///
+    /// After finish(), methods that add frames or change the output or settings
+    /// raise RuntimeError("encoder already finished"), until reset() is called.
+    ///
+    /// Parameters
+    /// ----------
+    /// timeout : float, optional
+    ///     Seconds to wait for the encoding to finish. No limit by default.
+    /// progress : bool
+    ///     If True, a tqdm progress bar of the frames written is shown while
+    ///     waiting, e.g. in notebooks. If tqdm isn't installed, nothing is shown.
+    #[args(timeout="None", progress=false)]
+    #[pyo3(text_signature = "(self, /, timeout=None, progress=False)")]
+    unsafe fn finish(&mut self, py: Python<'_>, timeout: Option<f64>, progress: bool) -> PyResult<PyFinishStats> {
+        self.check_not_finished()?;
+        check_output_set(self.has_output).map_err(exceptions::PyRuntimeError::new_err)?;
+        self.finish_output(py, timeout, progress)
+    }
+
+    /// Finalize the gif in the background, and iterate over the progress.
+    ///
+    /// Works like finish(), but returns an iterator over fractions from 0.0 to 1.0
+    /// of frames written so far. Errors are raised at the end of the iteration.
+    ///
+    /// Example:
+    ///     for progress in g.finish_iter():
+    ///         print(f"{progress:.0%}")
+    ///
+    /// No further methods should be called on this object after calling finish_iter().
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn finish_iter(&mut self, py: Python<'_>) -> PyResult<PyFinishIter> {
+        self.check_not_finished()?;
+        check_output_set(self.has_output).map_err(exceptions::PyRuntimeError::new_err)?;
+        self.check_indexed_frames()?;
+        self.add_merged_frames(py)?;
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        *self.callbacks.progress_sender.lock().unwrap() = Some(sender);
+
+        self.finished = true;
+        let handle = mem::replace(&mut self._handle, 0);
+        let callbacks = self.callbacks.clone();
+        let thread = thread::Builder::new().name("finish".into()).spawn(move || {
+            let res = unsafe { gifski_finish(handle as *const GifskiHandle) };
+            // disconnects the iterator
+            callbacks.progress_sender.lock().unwrap().take();
+            res
+        })?;
+
+        Ok(PyFinishIter {
+            callbacks: self.callbacks.clone(),
+            progress: receiver,
+            thread: Some(thread),
+            frame_count: self.frames_sent,
+        })
+    }
+
+    /// Finalize the gif without blocking the asyncio event loop.
+    ///
+    /// Works like finish(), but the waiting is done on another thread.
+    /// Must be called from a coroutine running in an event loop. The returned
+    /// future resolves to the same FinishStats as finish(), or raises its errors.
+    ///
+    /// Example:
+    ///     stats = await g.finish_async()
+    ///
+    /// No further methods should be called on this object after calling finish_async().
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn finish_async(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        self.check_not_finished()?;
+        check_output_set(self.has_output).map_err(exceptions::PyRuntimeError::new_err)?;
+        self.check_indexed_frames()?;
+        self.add_merged_frames(py)?;
+        let event_loop: PyObject = py.import("asyncio")?.call_method0("get_running_loop")?.into();
+        let future: PyObject = event_loop.call_method0(py, "create_future")?;
+        let resolve_future: PyObject = wrap_pyfunction!(resolve_future, py)?.into();
+
+        self.finished = true;
+        let handle = mem::replace(&mut self._handle, 0);
+        let callbacks = self.callbacks.clone();
+        let output_path = self.output_path.clone();
+        let created_at = self.created_at;
+        let result_future = future.clone_ref(py);
+        thread::Builder::new().name("finish".into()).spawn(move || {
+            let res = unsafe { gifski_finish(handle as *const GifskiHandle) };
+            callbacks.clear();
+            Python::with_gil(|py| {
+                let result = match callbacks.error.lock().unwrap().take() {
+                    Some(err) => Err(err),
+                    None if res != GifskiError::OK => Err(callbacks.error(res)),
+                    None => Ok(PyFinishStats::new(&callbacks, output_path.as_deref(), created_at)),
+                };
+                let (result, exception) = match result {
+                    Ok(stats) => (stats.into_py(py), py.None()),
+                    Err(err) => (py.None(), err.into_py(py)),
+                };
+                // the future can only be used from its event loop's thread
+                if let Err(err) = event_loop.call_method1(py, "call_soon_threadsafe", (resolve_future, result_future, result, exception)) {
+                    // the loop may have been closed already
+                    err.print(py);
+                }
+            });
+        })?;
+        Ok(future)
+    }
+
+    /// Finalize the gif in the background, and iterate over its data as it's written.
+    ///
+    /// This works only when no output has been set, like finish_to_bytes(),
+    /// but the gif doesn't have to be kept in memory. The iterator yields bytes
+    /// objects, and raises the encoding error if one happens. The encoder waits
+    /// while the chunks aren't being consumed. Dropping the iterator before the
+    /// end aborts the encoding.
+    ///
+    /// Example:
+    ///     for chunk in g.finish_chunks():
+    ///         response.write(chunk)
+    ///
+    /// No further methods should be called on this object after calling finish_chunks().
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn finish_chunks(&mut self, py: Python<'_>) -> PyResult<PyFinishChunks> {
+        self.check_not_finished()?;
+        if self.has_output {
+            return Err(error_from_code(GifskiError::INVALID_STATE));
+        }
+        self.ensure_output()?;
+        self.check_indexed_frames()?;
+        self.add_merged_frames(py)?;
+        let (sender, receiver) = crossbeam_channel::bounded(16);
+        // frames added so far have been written to memory
+        let buffered = match self.callbacks.output.lock().unwrap().replace(Output::Chunks(sender)) {
+            Some(Output::Memory(buf)) => buf,
+            _ => return Err(error_from_code(GifskiError::INVALID_STATE)),
+        };
+
+        self.finished = true;
+        let handle = mem::replace(&mut self._handle, 0);
+        let callbacks = self.callbacks.clone();
+        let thread = thread::Builder::new().name("finish".into()).spawn(move || {
+            let res = unsafe { gifski_finish(handle as *const GifskiHandle) };
+            // disconnects the iterator
+            callbacks.clear();
+            res
+        })?;
+
+        Ok(PyFinishChunks {
+            callbacks: self.callbacks.clone(),
+            buffered,
+            chunks: receiver,
+            thread: Some(thread),
+        })
+    }
+
+    /// Finalize the gif, and return it as bytes instead of writing it.
+    ///
+    /// This works only when no output has been set, and the frames have been
+    /// encoded in memory.
+    ///
+    /// No further methods should be called on this object after calling finish_to_bytes().
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn finish_to_bytes(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let gif = self.finish_to_vec(py)?;
+        Ok(PyBytes::new(py, &gif).into())
+    }
+
+    /// Finalize the gif, re-encoding it at lower quality until it's at most max_bytes long.
+    ///
+    /// This requires keep_frames=True, and no output, like finish_to_bytes().
+    /// The gif is first finished with the current settings. If it's too large,
+    /// the kept frames are encoded again in memory, searching for the highest
+    /// quality that fits, with lossy_quality and motion_quality lowered to it
+    /// too. Each attempt encodes all frames, so their number is limited.
+    ///
+    /// If no attempt fits, the smallest gif is returned, and a "size_exceeded"
+    /// EncoderWarning is given to the callback of set_warning_callback().
+    ///
+    /// Parameters
+    /// ----------
+    /// max_bytes : int
+    ///     Size limit of the gif.
+    /// max_attempts : int
+    ///     Number of times the gif may be encoded, including the first time.
+    ///
+    /// Returns
+    /// -------
+    /// bytes
+    ///     The gif.
+    #[args(max_attempts=6)]
+    #[pyo3(text_signature = "(self, max_bytes, /, max_attempts=6)")]
+    unsafe fn finish_to_size(&mut self, py: Python<'_>, max_bytes: u64, max_attempts: u32) -> PyResult<PyObject> {
+        use rgb::ComponentBytes;
+
+        if max_attempts == 0 {
+            return Err(exceptions::PyValueError::new_err("max_attempts must be at least 1"));
+        }
+        self.check_not_finished()?;
+        if self.kept_frames.is_none() {
+            return Err(exceptions::PyRuntimeError::new_err("finish_to_size() requires keep_frames=True"));
+        }
+        if self.has_output {
+            return Err(exceptions::PyRuntimeError::new_err("finish_to_size() returns the gif as bytes, so it can't be used with an output"));
+        }
+        // finishing clears the callbacks
+        let warning_callback = self.callbacks.warning.lock().unwrap().as_ref().map(|cb| cb.clone_ref(py));
+        let mut gif = self.finish_to_vec(py)?;
+        if gif.len() as u64 <= max_bytes {
+            return Ok(PyBytes::new(py, &gif).into());
+        }
+
+        let frames = self.kept_frames.as_ref().expect("checked");
+        let mut smallest = Vec::new();
+        let fitting_quality = highest_fitting_quality(self.settings.quality, max_attempts - 1, |quality| {
+            let settings = GifskiSettings { quality, ..self.settings };
+            let extra_settings = ExtraSettings {
+                motion_quality: quality.min(self.extra_settings.motion_quality),
+                lossy_quality: quality.min(self.extra_settings.lossy_quality),
+                ..self.extra_settings.clone()
+            };
+            // timestamps of the kept frames are already divided by speed
+            let mut encoder = Self::with_settings(settings, extra_settings, FrameOptions::default())?;
+            for frame in frames {
+                encoder.add_frame_rgba_slice(py, frame.pixels.as_bytes(), frame.timestamp)?;
+            }
+            let attempt = encoder.finish_to_vec(py)?;
+            if attempt.len() as u64 <= max_bytes {
+                gif = attempt;
+                return Ok(true);
+            }
+            if smallest.is_empty() || attempt.len() < smallest.len() {
+                smallest = attempt;
+            }
+            Ok(false)
+        })?;
+        if fitting_quality.is_none() {
+            if !smallest.is_empty() && smallest.len() < gif.len() {
+                gif = smallest;
+            }
+            let warning = PyEncoderWarning {
+                category: "size_exceeded".into(),
+                message: format!("the smallest gif that could be made is {} bytes, more than {} bytes", gif.len(), max_bytes),
+            };
+            if let Some(callback) = warning_callback {
+                callback.call1(py, (warning,))?;
+            }
+        }
+        Ok(PyBytes::new(py, &gif).into())
+    }
+
+    /// Finalize the gif with only one of the added frames, e.g. for a preview image.
+    ///
+    /// This requires keep_frames=True, and an output set with set_file_output().
+    /// The data written so far is discarded, and the file is replaced with a
+    /// gif of just the selected frame, so FinishStats report 1 frame written.
+    /// Other outputs can't be rewritten, so RuntimeError is raised for them.
+    ///
+    /// Example:
+    ///     g.finish_thumbnail(g.frame_count // 2)
+    ///
+    /// Parameters
+    /// ----------
+    /// index : int
+    ///     Index of an added frame, from 0 to frame_count-1.
+    #[args(index=0)]
+    #[pyo3(text_signature = "(self, /, index=0)")]
+    unsafe fn finish_thumbnail(&mut self, py: Python<'_>, index: usize) -> PyResult<PyFinishStats> {
+        use rgb::ComponentBytes;
+
+        self.check_not_finished()?;
+        let kept_frames = self.kept_frames.as_ref()
+            .ok_or_else(|| exceptions::PyRuntimeError::new_err("finish_thumbnail() requires keep_frames=True"))?;
+        if index >= kept_frames.len() {
+            return Err(exceptions::PyIndexError::new_err(format!("frame index {} is out of range, {} frames have been added", index, kept_frames.len())));
+        }
+        let path = match &self.output_path {
+            Some(path) if self.callbacks.tee_writers.lock().unwrap().is_empty() => path.clone(),
+            _ => return Err(exceptions::PyRuntimeError::new_err("finish_thumbnail() requires an output set with set_file_output(), because other outputs can't be rewritten")),
+        };
+        // copied, so that nothing is lost if reset() fails and the caller tries again
+        let pixels = kept_frames[index].pixels.clone();
+
+        // starts over with the same settings and callbacks
+        self.reset(py)?;
+        self.set_file_output(path, true)?;
+        self.add_frame_rgba_slice(py, pixels.as_bytes(), 0.0)?;
+        self.finish(py, None, false)
+    }
+
+    /// The colors of a palette for all added frames, as (r, g, b, a) tuples.
+    ///
+    /// gifski chooses a separate palette for every frame while encoding, so
+    /// there's no single palette in the gif. This quantizes the frames kept
+    /// with keep_frames=True together, at the same quality, which shows which
+    /// colors survive quantization. It can be called before or after finish().
+    ///
+    /// If set_palette() has been called, its colors are returned instead,
+    /// and keep_frames isn't needed.
+    ///
+    /// Returns
+    /// -------
+    /// list of (int, int, int, int) tuples
+    ///     Up to 256 colors.
+    #[pyo3(text_signature = "(self, /)")]
+    fn preview_palette(&self, py: Python<'_>) -> PyResult<Vec<(u8, u8, u8, u8)>> {
+        let palette = if !self.extra_settings.fixed_palette.is_empty() {
+            self.extra_settings.fixed_palette.clone()
+        } else {
+            let kept_frames = self.kept_frames.as_ref()
+                .ok_or_else(|| exceptions::PyRuntimeError::new_err("preview_palette() requires keep_frames=True or set_palette()"))?;
+            if kept_frames.is_empty() {
+                return Err(exceptions::PyRuntimeError::new_err("no frames have been added"));
+            }
+            let (width, height, quality) = (self.width, self.height, self.settings.quality);
+            py.allow_threads(|| combined_palette(kept_frames, width, height, quality))
+                .map_err(|err| exceptions::PyRuntimeError::new_err(format!("can't quantize the frames: {}", err)))?
+        };
+        Ok(palette.into_iter().map(|c| (c.r, c.g, c.b, c.a)).collect())
+    }
+
+    /// Approximate size in bytes of a gif with the frames added so far.
+    ///
+    /// This is an estimate for choosing settings before the real encoding. It
+    /// requires keep_frames=True, and encodes the kept frames again in memory,
+    /// with the same settings. With more than max_samples frames, only that
+    /// many frames, evenly spaced, are encoded, and the size is scaled up to
+    /// all frames. Frames far apart differ more than neighboring frames do,
+    /// so for animations with little motion the estimate tends to be too high.
+    /// It can be called before or after finish(), and it doesn't change the gif.
+    ///
+    /// Parameters
+    /// ----------
+    /// max_samples : int
+    ///     Number of frames to encode at most. Higher is slower, and more accurate.
+    ///
+    /// Returns
+    /// -------
+    /// int
+    ///     Estimated number of bytes.
+    #[args(max_samples=10)]
+    #[pyo3(text_signature = "(self, /, max_samples=10)")]
+    unsafe fn estimate_size(&self, py: Python<'_>, max_samples: usize) -> PyResult<u64> {
+        use rgb::ComponentBytes;
+
+        if max_samples == 0 {
+            return Err(exceptions::PyValueError::new_err("max_samples must be at least 1"));
+        }
+        let frames = self.kept_frames.as_ref()
+            .ok_or_else(|| exceptions::PyRuntimeError::new_err("estimate_size() requires keep_frames=True"))?;
+        if frames.is_empty() {
+            return Err(exceptions::PyRuntimeError::new_err("no frames have been added"));
+        }
+
+        let samples = sample_indices(frames.len(), max_samples);
+        // timestamps of the kept frames are already divided by speed,
+        // and with auto_rebase the first sample doesn't need to be at 0
+        let options = FrameOptions { auto_rebase: true, ..FrameOptions::default() };
+        let mut encoder = Self::with_settings(self.settings, self.extra_settings.clone(), options)?;
+        for &index in &samples {
+            let frame = &frames[index];
+            encoder.add_frame_rgba_slice(py, frame.pixels.as_bytes(), frame.timestamp)?;
+        }
+        let sample_size = encoder.finish_to_vec(py)?.len() as u64;
+        Ok(sample_size * frames.len() as u64 / samples.len() as u64)
+    }
+
+    /// Stop encoding, and discard the gif.
+    ///
+    /// The unfinished output file is removed. Frames can't be added after this,
+    /// and finish() will raise RuntimeError. Calling abort() again does nothing.
+    ///
+    /// Returns a FinishStats object with complete=False and the frames written
+    /// so far, or None if the gif has already been finished or aborted.
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn abort(&mut self, py: Python<'_>) -> Option<PyFinishStats> {
+        if self.finished {
+            return None;
+        }
+        self.abort_and_release(py);
+        *self.callbacks.output.lock().unwrap() = None;
+        // errors caused by aborting aren't interesting
+        self.callbacks.error.lock().unwrap().take();
+        Some(PyFinishStats::new(&self.callbacks, self.output_path.as_deref(), self.created_at))
+    }
+
+    /// Discard the gif and free the encoder, without the option of reusing it.
+    ///
+    /// This is like abort(), and the unfinished output file is removed too,
+    /// but afterwards all methods that add frames, change settings or finish
+    /// raise RuntimeError("encoder closed"), including reset(). Calling close()
+    /// again, or after finish(), only marks the object as closed.
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn close(&mut self, py: Python<'_>) {
+        self.abort(py);
+        self.closed = true;
+    }
+
+    /// Start a new gif, with the same settings and callbacks.
+    ///
+    /// This allows reusing the object after finish(). If the current gif hasn't
+    /// been finished, it's aborted. The frame count and timestamps start from 0.
+    ///
+    /// The callbacks kept are the ones from set_progress_callback(),
+    /// set_error_callback() and set_warning_callback(). Outputs aren't kept,
+    /// because they have the previous gif: the output, including the writer
+    /// of set_write_callback(), has to be set again, and so do the writers
+    /// of add_write_callback().
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn reset(&mut self, py: Python<'_>) -> PyResult<()> {
+        if self.closed {
+            return Err(exceptions::PyRuntimeError::new_err("encoder closed"));
+        }
+        let progress = self.callbacks.progress.lock().unwrap().as_ref().map(|cb| cb.clone_ref(py));
+        let error_message = self.callbacks.error_message.lock().unwrap().as_ref().map(|cb| cb.clone_ref(py));
+        let warning = self.callbacks.warning.lock().unwrap().as_ref().map(|cb| cb.clone_ref(py));
+        self.abort(py);
+
+        let callbacks = Arc::new(Callbacks {
+            progress: Mutex::new(progress),
+            error_message: Mutex::new(error_message),
+            warning: Mutex::new(warning),
+            ..Callbacks::default()
+        });
+        self._handle = new_handle(&self.settings, &self.extra_settings, &callbacks)?;
+        // the old handle has been freed, so nothing uses the old callbacks any more
+        self.callbacks = callbacks;
+        self.frame_count = 0;
+        self.frames_sent = 0;
+        self.timestamp_origin = 0.0;
+        self.last_timestamp = None;
+        self.timestamps.clear();
+        self.elapsed = 0.0;
+        self.last_timestamp_ms = None;
+        self.canvas = Vec::new();
+        self.indexed_frames.get_mut().unwrap().clear();
+        if self.dedupe.is_some() {
+            self.dedupe = Some(PreviousFrame::default());
+        }
+        if self.kept_frames.is_some() {
+            self.kept_frames = Some(Vec::new());
+        }
+        self.finished = false;
+        self.has_output = false;
+        self.output_path = None;
+        self.owns_output_file = false;
+        self.created_at = Instant::now();
+        Ok(())
+    }
+
+    /// Set a function to be called after each frame is written.
+    ///
+    /// The callback receives the number of frames written so far.
+    /// If it returns a falsy value, the encoding is aborted, and finish() will
+    /// raise an error. Exceptions raised by the callback abort the encoding too,
+    /// and are re-raised from finish().
+    ///
+    /// The callback is called from gifski's writer thread, while frames are being
+    /// added and during finish().
+    ///
+    /// Parameters
+    /// ----------
+    /// callback : callable
+    ///     Function taking the number of frames written, and returning a bool.
+    #[pyo3(text_signature = "(self, callback, /)")]
+    fn set_progress_callback(&self, py: Python<'_>, callback: PyObject) -> PyResult<()> {
+        if !callback.as_ref(py).is_callable() {
+            return Err(exceptions::PyTypeError::new_err("callback must be callable"));
+        }
+        *self.callbacks.progress.lock().unwrap() = Some(callback);
+        Ok(())
+    }
+
+    /// Set a function to be called with gifski's error messages.
+    ///
+    /// The messages explain why a method failed, or why writing of the gif has
+    /// failed. By default they're printed to stderr. The callback can forward
+    /// them to the logging module instead:
+    ///     g.set_error_callback(logging.getLogger("gifski").warning)
+    ///
+    /// The callback may be called from gifski's writer thread.
+    ///
+    /// Parameters
+    /// ----------
+    /// callback : callable
+    ///     Function taking the message as a str.
+    #[pyo3(text_signature = "(self, callback, /)")]
+    fn set_error_callback(&self, py: Python<'_>, callback: PyObject) -> PyResult<()> {
+        if !callback.as_ref(py).is_callable() {
+            return Err(exceptions::PyTypeError::new_err("callback must be callable"));
+        }
+        *self.callbacks.error_message.lock().unwrap() = Some(callback);
+        Ok(())
+    }
+
+    /// Set a function to be called about problems that didn't stop the encoding.
+    ///
+    /// The callback receives an EncoderWarning with a `category` and a `message`.
+    /// Currently the categories are:
+    ///  - "frames_skipped": frames identical to the previous frame, or shorter than
+    ///    1/100th of a second, have been left out of the gif.
+    ///  - "delay_clamped": frames longer than 300 seconds have been shortened.
+    ///  - "size_exceeded": finish_to_size() couldn't make the gif small enough.
+    ///
+    /// Warnings are reported during finish(), from gifski's writer thread.
+    /// By default they're ignored.
+    ///
+    /// Parameters
+    /// ----------
+    /// callback : callable
+    ///     Function taking an EncoderWarning.
+    #[pyo3(text_signature = "(self, callback, /)")]
+    fn set_warning_callback(&self, py: Python<'_>, callback: PyObject) -> PyResult<()> {
+        if !callback.as_ref(py).is_callable() {
+            return Err(exceptions::PyTypeError::new_err("callback must be callable"));
+        }
+        *self.callbacks.warning.lock().unwrap() = Some(callback);
+        Ok(())
+    }
+
+    /// The last error message logged by gifski, or None.
+    ///
+    /// These are the messages given to the callback of set_error_callback().
+    /// GifskiError exceptions include the message logged since the previous
+    /// exception, if there is one, in their text and as `message`.
+    #[pyo3(text_signature = "(self, /)")]
+    fn get_last_error(&self) -> Option<String> {
+        self.callbacks.last_error_message.lock().unwrap().clone()
+    }
+
+    /// Name of a GifskiError code from gifski.h, e.g. "INVALID_STATE" for 2.
+    ///
+    /// Returns "UNKNOWN" for codes that aren't in the GifskiError enum.
+    #[staticmethod]
+    #[pyo3(text_signature = "(code, /)")]
+    fn error_name(code: i64) -> String {
+        error_code_name(code)
+    }
+
+    /// Create a new Gifski object with the same settings.
+    ///
+    /// The copy has the width, height, quality, fast, repeat, fps, resize, fit,
+    /// background, dedupe, max_frames, motion_quality, lossy_quality, dither, palette,
+    /// transparent_color, comment and delay_rounding of this object, but
+    /// no frames or output. Callbacks are not copied.
+    /// copy.copy() does the same.
+    #[pyo3(text_signature = "(self, /)")]
+    unsafe fn clone_settings(&self) -> PyResult<PyGifski> {
+        Self::with_settings(self.settings, self.extra_settings.clone(), self.frame_options())
+    }
+
+    unsafe fn __copy__(&self) -> PyResult<PyGifski> {
+        self.clone_settings()
+    }
+
+    /// Pickling keeps the settings, as returned by settings().
+    /// Unpickling creates a new Gifski without frames or output.
+    fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<&'py pyo3::types::PyDict> {
+        if self.frame_count > 0 || !self.indexed_frames.lock().unwrap().is_empty() {
+            return Err(exceptions::PyTypeError::new_err("Gifski objects can't be pickled after frames have been added"));
+        }
+        self.settings(py)
+    }
+
+    /// The values are checked again, like arguments of Gifski()
+    unsafe fn __setstate__(&mut self, state: &pyo3::types::PyDict) -> PyResult<()> {
+        let get = |key: &str| state.get_item(key)
+            .ok_or_else(|| exceptions::PyValueError::new_err(format!("the pickled settings have no {}", key)));
+        let quality = quality_arg(get("quality")?.extract()?, "quality")?;
+        let fast_level = fast_level(FastArg::Level(get("fast")?.extract()?))?;
+        let settings = GifskiSettings {
+            width: get("width")?.extract()?,
+            height: get("height")?.extract()?,
+            quality,
+            fast: fast_level == 3,
+            repeat: repeat_arg(get("repeat")?.extract()?)?,
+        };
+        let extra_settings = ExtraSettings {
+            fast_level,
+            motion_quality: quality_arg(get("motion_quality")?.extract()?, "motion_quality")?,
+            lossy_quality: quality_arg(get("lossy_quality")?.extract()?, "lossy_quality")?,
+            dithering_level: get("dither")?.extract::<Option<&PyAny>>()?.map(dithering_level).transpose()?,
+            fixed_palette: get("palette")?.extract::<Option<_>>()?.map(palette_arg).transpose()?.unwrap_or_default(),
+            transparent_color: get("transparent_color")?.extract::<Option<_>>()?.map(color_arg).transpose()?,
+            comment: get("comment")?.extract::<Option<&str>>()?.map(comment_bytes).transpose().map_err(exceptions::PyValueError::new_err)?,
+            delay_rounding: delay_rounding_arg(get("delay_rounding")?.extract()?)?,
+        };
+        let background = get("background")?.extract::<Option<_>>()?.map(color_arg).transpose()?;
+        let options = FrameOptions {
+            fps: get("fps")?.extract::<Option<_>>()?.map(fps_arg).transpose()?,
+            fit: fit_arg(get("fit")?.extract()?, background)?,
+            dedupe: get("dedupe")?.extract()?,
+            max_frames: get("max_frames")?.extract::<Option<_>>()?.map(max_frames_arg).transpose()?,
+            keep_frames: get("keep_frames")?.extract()?,
+            speed: speed_arg(get("speed")?.extract()?)?,
+            auto_rebase: get("auto_rebase")?.extract()?,
+            alpha_threshold: get("alpha_threshold")?.extract::<Option<_>>()?.map(alpha_threshold_arg).transpose()?,
+        };
+        // the old handle is freed when it's dropped
+        *self = Self::with_settings(settings, extra_settings, options)?;
+        Ok(())
+    }
+
+    fn __getnewargs__(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Made from the stored settings without using the handle, so it works after finish() too
+    fn __repr__(&self) -> String {
+        let GifskiSettings { width, height, quality, repeat, .. } = self.settings;
+        let state = match (self.finished, self.callbacks.aborted.load(Ordering::SeqCst)) {
+            _ if self.closed => ", closed",
+            (false, _) => "",
+            (true, false) => ", finished",
+            (true, true) => ", aborted",
+        };
+        format!("Gifski(width={}, height={}, quality={}, fast={}, repeat={}, frames={}{})", width, height, quality, self.extra_settings.fast_level, repeat, self.frame_count(), state)
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Calls finish(), unless it has been called already.
+    ///
+    /// If the block raised an exception, the encoding is aborted instead,
+    /// and the unfinished output file is removed.
+    unsafe fn __exit__(&mut self, py: Python<'_>, exc_type: Option<&PyAny>, _exc_value: Option<&PyAny>, _traceback: Option<&PyAny>) -> PyResult<bool> {
+        if self.finished {
+            return Ok(false);
+        }
+        if exc_type.is_some() {
+            self.abort_and_release(py);
+            return Ok(false);
+        }
+        self.finish(py, None, false)?;
+        Ok(false)
+    }
+}
+
+impl PyGifski {
+    /// Arguments other than width and height must have been validated already
+    unsafe fn with_settings(settings: GifskiSettings, extra_settings: ExtraSettings, options: FrameOptions) -> PyResult<Self> {
+        let FrameOptions { fps, fit, dedupe, max_frames, keep_frames, speed, auto_rebase, alpha_threshold } = options;
+        let GifskiSettings { width, height, .. } = settings;
+        if width == 0 || height == 0 {
+            return Err(exceptions::PyValueError::new_err("width and height must be greater than 0"));
+        }
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(exceptions::PyValueError::new_err(format!("width and height must be at most {}", MAX_DIMENSION)));
+        }
+
+        let callbacks = Arc::<Callbacks>::default();
+        let handle = new_handle(&settings, &extra_settings, &callbacks)?;
+
+        Ok(PyGifski {
+            _handle: handle,
+            settings,
+            extra_settings,
+            width,
+            height,
+            frame_count: 0,
+            frames_sent: 0,
+            max_frames,
+            fps,
+            speed,
+            fit,
+            alpha_threshold,
+            auto_rebase,
+            timestamp_origin: 0.0,
+            last_timestamp: None,
+            timestamps: Vec::new(),
+            elapsed: 0.0,
+            last_timestamp_ms: None,
+            canvas: Vec::new(),
+            dedupe: if dedupe { Some(PreviousFrame::default()) } else { None },
+            kept_frames: if keep_frames { Some(Vec::new()) } else { None },
+            indexed_frames: Mutex::default(),
+            finished: false,
+            closed: false,
+            has_output: false,
+            output_path: None,
+            owns_output_file: false,
+            created_at: Instant::now(),
+            callbacks,
+        })
+    }
+
+    /// The options given to `with_settings`
+    fn frame_options(&self) -> FrameOptions {
+        FrameOptions {
+            fps: self.fps,
+            fit: self.fit,
+            dedupe: self.dedupe.is_some(),
+            max_frames: self.max_frames,
+            keep_frames: self.kept_frames.is_some(),
+            speed: self.speed,
+            auto_rebase: self.auto_rebase,
+            alpha_threshold: self.alpha_threshold,
+        }
+    }
+
+    /// Returns the timestamp relative to the first frame's if auto_rebase is enabled
+    fn check_new_frame(&mut self, timestamp: f64) -> PyResult<f64> {
+        self.check_not_finished()?;
+        if !self.indexed_frames.lock().unwrap().is_empty() {
+            return Err(exceptions::PyRuntimeError::new_err("add_frame_rgba_at() can't be mixed with other add_frame_* methods"));
+        }
+        if self.auto_rebase && self.frame_count == 0 {
+            self.timestamp_origin = timestamp;
+        }
+        let timestamp = timestamp - self.timestamp_origin;
+        check_frame_limit(self.frame_count, self.max_frames)
+            .and_then(|_| validate_timestamp(timestamp, self.last_timestamp, self.frame_count))
+            .map_err(exceptions::PyValueError::new_err)?;
+        Ok(timestamp)
+    }
+
+    fn frame_added(&mut self, timestamp: f64) {
+        self.frame_count += 1;
+        self.timestamps.push(timestamp);
+        self.frames_sent += 1;
+        self.last_timestamp = Some(timestamp);
+    }
+
+    /// Counts the frame from `add_rgba_frame`, or raises its error
+    fn frame_added_or_merged(&mut self, res: Result<bool, GifskiError>, timestamp: f64) -> PyResult<()> {
+        if res.map_err(|code| self.callbacks.error(code))? {
+            self.frame_added(timestamp);
+        } else {
+            self.frame_count += 1;
+            self.timestamps.push(timestamp);
+            self.last_timestamp = Some(timestamp);
+            self.callbacks.frames_merged.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    /// Shows a tqdm bar, which is updated by the progress callback. Does nothing without tqdm.
+    fn start_progress_bar(&self, py: Python<'_>) -> PyResult<()> {
+        let tqdm = match py.import("tqdm") {
+            Ok(tqdm) => tqdm,
+            Err(_) => return Ok(()),
+        };
+        let kwargs = pyo3::types::PyDict::new(py);
+        kwargs.set_item("total", self.frames_sent)?;
+        // frames are encoded while they're added
+        kwargs.set_item("initial", self.callbacks.frames_written.load(Ordering::SeqCst))?;
+        kwargs.set_item("unit", "frame")?;
+        let bar = tqdm.call_method("tqdm", (), Some(kwargs))?;
+        *self.callbacks.progress_bar.lock().unwrap() = Some(bar.into());
+        Ok(())
+    }
+
+    /// Frames from add_frame_rgba_at() must have all indices, otherwise the encoder would wait for the missing ones
+    fn check_indexed_frames(&mut self) -> PyResult<()> {
+        let indexed_frames = self.indexed_frames.get_mut().unwrap();
+        if let Some(index) = missing_frame_index(indexed_frames.keys().copied()) {
+            return Err(exceptions::PyValueError::new_err(format!("frame {} hasn't been added, add_frame_rgba_at() must be called for every index below the highest one", index)));
+        }
+        if !indexed_frames.is_empty() {
+            self.frames_sent = indexed_frames.len() as u32;
+        }
+        Ok(())
+    }
+
+    /// If the last frames have been merged by dedupe, adds the previous frame again with the last timestamp,
+    /// because otherwise the previous frame would be displayed only as long as a single frame.
+    /// The encoder skips the identical frame, but keeps the time.
+    unsafe fn add_merged_frames(&mut self, py: Python<'_>) -> PyResult<()> {
+        let (handle, frame_number, width, height) = (self._handle, self.frames_sent, self.width, self.height);
+        let previous = match &mut self.dedupe {
+            Some(previous) => previous,
+            None => return Ok(()),
+        };
+        let timestamp = match previous.merged_timestamp.take() {
+            Some(timestamp) => timestamp,
+            None => return Ok(()),
+        };
+        let pixels = &previous.pixels;
+        let success = py.allow_threads(|| {
+            gifski_add_frame_rgba(handle as *const GifskiHandle, frame_number, width, height, pixels.as_ptr(), timestamp)
+        });
+        if success != GifskiError::OK {
+            return Err(self.callbacks.error(success));
+        }
+        self.frames_sent += 1;
+        Ok(())
+    }
+
+    /// finish() without checking the output, which may be in memory
+    unsafe fn finish_output(&mut self, py: Python<'_>, timeout: Option<f64>, progress: bool) -> PyResult<PyFinishStats> {
+        self.check_not_finished()?;
+        if let Some(timeout) = timeout {
+            if !(timeout >= 0.0 && timeout.is_finite()) {
+                return Err(exceptions::PyValueError::new_err("timeout must be a number of seconds >= 0"));
+            }
+        }
+        self.check_indexed_frames()?;
+        self.add_merged_frames(py)?;
+        if progress {
+            self.start_progress_bar(py)?;
+        }
+        // capped, because Instant can't be far in the future
+        let deadline = timeout.map(|timeout| Instant::now() + Duration::from_secs_f64(timeout.min(u32::MAX as f64)));
+        let callbacks = self.callbacks.clone();
+        let success = self.release(|handle| finish_interruptible(py, handle, &callbacks, deadline));
+        let bar = self.callbacks.progress_bar.lock().unwrap().take();
+        if let Some(bar) = bar {
+            bar.call_method0(py, "close")?;
+        }
+        if let Some(err) = self.callbacks.error.lock().unwrap().take() {
+            return Err(err);
+        }
+        if success == GifskiError::OK {
+            return Ok(PyFinishStats::new(&self.callbacks, self.output_path.as_deref(), self.created_at));
+        }
+
+        return Err(self.callbacks.error(success));
+    }
+
+    #[cfg(feature = "png")]
+    unsafe fn encode_png_files(py: Python<'_>, paths: Vec<PathBuf>, fps: f64, output: PathBuf, quality: i64) -> PyResult<PyFinishStats> {
+        let first_path = paths.first().ok_or_else(|| exceptions::PyValueError::new_err("paths must not be empty"))?;
+        let (width, height) = read_png_dimensions(first_path)?;
+        // checked upfront, so that a mismatch doesn't leave an unfinished file
+        for path in &paths[1..] {
+            let size = read_png_dimensions(path)?;
+            if size != (width, height) {
+                return Err(exceptions::PyValueError::new_err(format!("{} has size {:?}, but {} has size {:?}", path.display(), size, first_path.display(), (width, height))));
+            }
+        }
+
+        let mut g = Self::new(width, height, QualityArg::Int(quality), FastArg::Bool(false), 0, Some(fps), None, None, None, false, None, None, false, None, None, false, 1.0, "error", None, false, None, "round")?;
+        g.set_file_output(output, true)?;
+        for (index, path) in paths.into_iter().enumerate() {
+            g.add_frame_png_file(py, path.clone(), index as f64 / fps)
+                .map_err(|err| frame_error(py, err, index, Some(&path)))?;
+        }
+        g.finish(py, None, false)
+    }
+
+    /// The handle has been freed by `gifski_finish`, so nothing can be done with it until reset()
+    fn check_not_finished(&self) -> PyResult<()> {
+        if self.closed {
+            return Err(exceptions::PyRuntimeError::new_err("encoder closed"));
+        }
+        if self.finished {
+            let msg = if self.callbacks.aborted.load(Ordering::SeqCst) { "encoder already aborted" } else { "encoder already finished" };
+            return Err(exceptions::PyRuntimeError::new_err(msg));
+        }
+        Ok(())
+    }
+
+    fn callbacks_ptr(&self) -> *mut c_void {
+        Arc::as_ptr(&self.callbacks) as *mut c_void
+    }
+
+    unsafe fn add_frame_rgba_slice(&mut self, py: Python<'_>, pixels: &[u8], timestamp: f64) -> PyResult<()> {
+        let (handle, frame_number, width, height, speed) = (self._handle, self.frames_sent, self.width, self.height, self.speed);
+        check_not_empty(pixels)?;
+        if pixels.len() % 4 != 0 {
+            return Err(exceptions::PyValueError::new_err("pixels must be in RGBA format, 4 bytes per pixel"));
+        }
+        if frame_len(self.width, self.height, 4) != Some(pixels.len()) {
+            return Err(exceptions::PyValueError::new_err("pixel width*height doesn't match the width*height used during construction"));
+        }
+        let timestamp = self.check_new_frame(timestamp)?;
+
+        self.ensure_output()?;
+        let pixels = cast_pixels::<RGBA8>(pixels);
+        let copies = FrameCopies { previous: self.dedupe.as_mut(), kept: self.kept_frames.as_mut(), alpha_threshold: self.alpha_threshold };
+        let res = py.allow_threads(|| {
+            add_rgba_frame(handle, frame_number, width, height, pixels, timestamp / speed, copies)
+        });
+        self.frame_added_or_merged(res, timestamp)
+    }
+
+    /// The add_frame_* methods for pixels in other formats than RGBA.
+    ///
+    /// `to_rgba` converts the pixels without holding the GIL. `add_direct` is used instead,
+    /// if given, when the frame isn't copied for dedupe, keep_frames or alpha_threshold.
+    unsafe fn add_frame_converted(&mut self, py: Python<'_>, pixels: &[u8], timestamp: f64, format: &str, bytes_per_pixel: u32,
+            to_rgba: impl FnOnce(&[u8]) -> Vec<RGBA8> + Send, add_direct: Option<AddFrameDirect>) -> PyResult<()> {
+        let (handle, frame_number, width, height, speed) = (self._handle, self.frames_sent, self.width, self.height, self.speed);
+        check_not_empty(pixels)?;
+        if pixels.len() % bytes_per_pixel as usize != 0 {
+            return Err(exceptions::PyValueError::new_err(format!("pixels must be in {} format, {} bytes per pixel", format, bytes_per_pixel)));
+        }
+        if frame_len(self.width, self.height, bytes_per_pixel) != Some(pixels.len()) {
+            return Err(exceptions::PyValueError::new_err("pixel width*height doesn't match the width*height used during construction"));
+        }
+        let timestamp = self.check_new_frame(timestamp)?;
+
+        self.ensure_output()?;
+        let copies = FrameCopies { previous: self.dedupe.as_mut(), kept: self.kept_frames.as_mut(), alpha_threshold: self.alpha_threshold };
+        let res = py.allow_threads(|| match (copies, add_direct) {
+            (FrameCopies { previous: None, kept: None, alpha_threshold: None }, Some(add_direct)) => frame_result(add_direct(handle, frame_number, width, height, pixels, timestamp / speed)),
+            (copies, _) => add_rgba_frame(handle, frame_number, width, height, &to_rgba(pixels), timestamp / speed, copies),
+        });
+        self.frame_added_or_merged(res, timestamp)
+    }
+
+    /// The gif encoded in memory, when no output has been set
+    unsafe fn finish_to_vec(&mut self, py: Python<'_>) -> PyResult<Vec<u8>> {
+        if self.has_output {
+            return Err(error_from_code(GifskiError::INVALID_STATE));
+        }
+        self.ensure_output()?;
+        self.finish_output(py, None, false)?;
+
+        match self.callbacks.output.lock().unwrap().take() {
+            Some(Output::Memory(buf)) => Ok(buf),
+            _ => Err(error_from_code(GifskiError::INVALID_STATE)),
+        }
+    }
+
+    /// Scales or pads the frame to the size of the gif, depending on fit
+    unsafe fn add_frame_rgba_resized(&mut self, py: Python<'_>, pixels: &[u8], src_width: u32, src_height: u32, timestamp: f64) -> PyResult<()> {
+        let (handle, frame_number, width, height, speed) = (self._handle, self.frames_sent, self.width, self.height, self.speed);
+        if src_width == 0 || src_height == 0 || frame_len(src_width, src_height, 4) != Some(pixels.len()) {
+            return Err(exceptions::PyValueError::new_err("pixels don't match the frame size"));
+        }
+        let timestamp = self.check_new_frame(timestamp)?;
+
+        self.ensure_output()?;
+        let pixels = cast_pixels::<RGBA8>(pixels);
+        let fit = self.fit;
+        let copies = FrameCopies { previous: self.dedupe.as_mut(), kept: self.kept_frames.as_mut(), alpha_threshold: self.alpha_threshold };
+        let res = py.allow_threads(|| {
+            let resized = match fit {
+                Fit::Pad(background) => letterbox(pixels, src_width, src_height, width, height, background),
+                _ => resize_pixels(pixels, src_width, src_height, width, height),
+            };
+            let resized = resized.map_err(|_| GifskiError::INVALID_INPUT)?;
+            add_rgba_frame(handle, frame_number, width, height, &resized, timestamp / speed, copies)
+        });
+        self.frame_added_or_merged(res, timestamp)
+    }
+
+    /// The progress callback can only be set before the output, so it's always installed to allow aborting
+    unsafe fn install_progress_callback(&self) {
+        gifski_set_progress_callback(self._handle as *const GifskiHandle, progress_trampoline, self.callbacks_ptr());
+    }
+
+    unsafe fn start_write_callback(&self, output: Output) -> GifskiError {
+        *self.callbacks.output.lock().unwrap() = Some(output);
+        self.install_progress_callback();
+        let success = gifski_set_write_callback(self._handle as *const GifskiHandle, Some(write_trampoline), self.callbacks_ptr());
+        if success != GifskiError::OK {
+            *self.callbacks.output.lock().unwrap() = None;
+        }
+        success
+    }
+
+    /// Frames added without an output have been encoded in memory, and are written to the output first
+    unsafe fn start_output(&self, output: Output) -> GifskiError {
+        if self.is_encoding_in_memory() {
+            return self.callbacks.replace_memory_output(output);
+        }
+        self.start_write_callback(output)
+    }
+
+    /// True if frames have been added before setting an output
+    fn is_encoding_in_memory(&self) -> bool {
+        !self.has_output && matches!(&*self.callbacks.output.lock().unwrap(), Some(Output::Memory(_)))
+    }
+
+    /// None of the outputs has been set yet, but the gif may be encoded in memory
+    fn can_set_output(&self) -> bool {
+        !self.has_output && matches!(&*self.callbacks.output.lock().unwrap(), None | Some(Output::Memory(_)))
+    }
+
+    /// gifski writes files itself, so the file is opened here to make the write callback see its data,
+    /// or to move the gif encoded in memory to the file
+    unsafe fn set_file_output_with_tee(&mut self, destination: PathBuf, overwrite: bool) -> PyResult<()> {
+        let file = if overwrite {
+            std::fs::File::create(&destination)
+        } else {
+            std::fs::OpenOptions::new().write(true).create_new(true).open(&destination)
+        };
+        let file = file.map_err(|err| error_from_code(err.kind().into()))?;
+        let success = self.start_output(Output::File(file));
+        if success == GifskiError::OK {
+            self.has_output = true;
+            self.output_path = Some(destination);
+            self.owns_output_file = true;
+            return Ok(());
+        }
+
+        let _ = std::fs::remove_file(&destination);
+        Err(self.callbacks.error(success))
+    }
+
+    /// gifski can't buffer frames before writing has started,
+    /// so without an output the gif is encoded in memory.
+    unsafe fn ensure_output(&self) -> PyResult<()> {
+        self.check_not_finished()?;
+        if self.has_output || self.callbacks.output.lock().unwrap().is_some() {
+            return Ok(());
+        }
+        let success = self.start_write_callback(Output::Memory(Vec::new()));
+        if success == GifskiError::OK {
+            return Ok(());
+        }
+
+        Err(self.callbacks.error(success))
+    }
+
+    /// Makes the writer stop at the next frame, and frees the handle without finishing the output.
+    ///
+    /// Signals aren't checked here, because nothing would raise their exceptions
+    /// after abort(), close(), reset() or dropping, so they're left for Python to handle.
+    unsafe fn abort_and_release(&mut self, py: Python<'_>) {
+        self.callbacks.aborted.store(true, Ordering::SeqCst);
+        self.release(|handle| py.allow_threads(|| gifski_finish(handle as *const GifskiHandle)));
+    }
+
+    /// Finishes the encoder with `finish`, which gets the handle, and frees the handle. Safe to call more than once.
+    unsafe fn release(&mut self, finish: impl FnOnce(usize) -> GifskiError) -> GifskiError {
+        if self.finished {
+            return GifskiError::NULL_ARG;
+        }
+        self.finished = true;
+        let handle = mem::replace(&mut self._handle, 0);
+        let res = finish(handle);
+        // closes the file
+        self.callbacks.clear();
+        if res != GifskiError::OK && self.owns_output_file {
+            if let Some(path) = &self.output_path {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+        res
+    }
+}
+
+/// Adds the frame, unless it's identical to the previous frame. Returns false if it has been merged into the previous frame.
+///
+/// The kept copy is made either way, because merged frames still have their index.
+unsafe fn add_rgba_frame(handle: usize, frame_number: u32, width: u32, height: u32, pixels: &[RGBA8], timestamp: f64, copies: FrameCopies<'_>) -> Result<bool, GifskiError> {
+    let pixels = thresholded_alpha(pixels, copies.alpha_threshold);
+    let pixels = &pixels[..];
+    let added = match copies.previous {
+        Some(previous) if is_duplicate_frame(&previous.pixels, pixels) => {
+            previous.merged_timestamp = Some(timestamp);
+            false
+        },
+        previous => {
+            frame_result(gifski_add_frame_rgba(handle as *const GifskiHandle, frame_number, width, height, pixels.as_ptr(), timestamp))?;
+            if let Some(previous) = previous {
+                previous.pixels.clear();
+                previous.pixels.extend_from_slice(pixels);
+                previous.merged_timestamp = None;
+            }
+            true
+        },
+    };
+    if let Some(kept) = copies.kept {
+        kept.push(KeptFrame { pixels: pixels.to_vec(), timestamp });
+    }
+    Ok(added)
+}
+
+/// Makes alpha binary for alpha_threshold: 0 below the threshold, 255 otherwise
+fn thresholded_alpha(pixels: &[RGBA8], alpha_threshold: Option<u8>) -> Cow<'_, [RGBA8]> {
+    match alpha_threshold {
+        Some(threshold) => pixels.iter().map(|px| RGBA8 { a: if px.a < threshold { 0 } else { 255 }, ..*px }).collect(),
+        None => Cow::Borrowed(pixels),
+    }
+}
+
+/// Before the first frame there's nothing to compare with
+fn is_duplicate_frame(previous: &[RGBA8], pixels: &[RGBA8]) -> bool {
+    !previous.is_empty() && previous == pixels
+}
+
+fn frame_result(res: GifskiError) -> Result<bool, GifskiError> {
+    match res {
+        GifskiError::OK => Ok(true),
+        err => Err(err),
+    }
+}
+
+/// Runs `gifski_finish`, and aborts it on KeyboardInterrupt (or other exceptions from signal handlers),
+/// or after the deadline. The exception is then set as the callbacks' error.
+///
+/// Signals are handled only on the main thread, so the waiting for gifski is done on another thread.
+unsafe fn finish_interruptible(py: Python<'_>, handle: usize, callbacks: &Callbacks, deadline: Option<Instant>) -> GifskiError {
+    let (done_sender, done) = crossbeam_channel::bounded(1);
+    let thread = thread::Builder::new().name("finish".into()).spawn(move || {
+        let res = gifski_finish(handle as *const GifskiHandle);
+        let _ = done_sender.send(());
+        res
+    });
+    let thread = match thread {
+        Ok(thread) => thread,
+        // callbacks need the GIL to run, and gifski_finish waits for them
+        Err(_) => return py.allow_threads(|| gifski_finish(handle as *const GifskiHandle)),
+    };
+    let mut aborted = false;
+    loop {
+        let wait = match deadline {
+            Some(deadline) if !aborted => deadline.saturating_duration_since(Instant::now()).min(Duration::from_millis(100)),
+            _ => Duration::from_millis(100),
+        };
+        if !matches!(py.allow_threads(|| done.recv_timeout(wait)), Err(crossbeam_channel::RecvTimeoutError::Timeout)) {
+            break;
+        }
+        if aborted {
+            continue;
+        }
+        let err = match py.check_signals() {
+            Err(err) => err,
+            Ok(()) if deadline.map_or(false, |deadline| Instant::now() >= deadline) => {
+                exceptions::PyTimeoutError::new_err("the gif hasn't been finished before the timeout")
+            },
+            Ok(()) => continue,
+        };
+        // the writer stops at the next frame
+        callbacks.aborted.store(true, Ordering::SeqCst);
+        callbacks.set_error(err);
+        aborted = true;
+    }
+    py.allow_threads(|| thread.join()).unwrap_or(GifskiError::THREAD_LOST)
+}
+
+/// Creates the encoder, with error messages going to the callbacks
+unsafe fn new_handle(settings: &GifskiSettings, extra_settings: &ExtraSettings, callbacks: &Arc<Callbacks>) -> PyResult<usize> {
+    let handle = gifski_new(settings);
+    if handle.is_null() {
+        return Err(exceptions::PyValueError::new_err("invalid settings"));
+    }
+    gifski_set_error_message_callback(handle, error_message_trampoline, Arc::as_ptr(callbacks) as *mut c_void);
+    gifski_set_warning_callback(handle, warning_trampoline, Arc::as_ptr(callbacks) as *mut c_void);
+
+    let ExtraSettings { fast_level, motion_quality, lossy_quality, dithering_level, fixed_palette, transparent_color, comment, delay_rounding } = extra_settings;
+    let mut results = vec![gifski_set_fast_level(handle, *fast_level), gifski_set_motion_quality(handle, *motion_quality), gifski_set_lossy_quality(handle, *lossy_quality), gifski_set_delay_rounding(handle, *delay_rounding)];
+    if let Some(level) = *dithering_level {
+        results.push(gifski_set_dithering_level(handle, level));
+    }
+    if !fixed_palette.is_empty() {
+        results.push(gifski_set_fixed_palette(handle, fixed_palette.as_ptr(), fixed_palette.len() as u32));
+    }
+    if let Some(color) = transparent_color {
+        results.push(gifski_set_transparent_color(handle, color));
+    }
+    if let Some(comment) = comment {
+        results.push(gifski_set_comment(handle, comment.as_ptr()));
+    }
+    for success in results {
+        if success != GifskiError::OK {
+            gifski_finish(handle);
+            return Err(callbacks.error(success));
+        }
+    }
+    Ok(handle as usize)
+}
+
+impl Drop for PyGifski {
+    fn drop(&mut self) {
+        // gifski_finish is the only way to free the handle,
+        // but an abandoned encoder shouldn't write anything
+        Python::with_gil(|py| unsafe { self.abort_and_release(py) });
+    }
+}
+
+/// Says which frame has failed, without replacing the exception, so its type, attributes
+/// (like `code` of GifskiError) and traceback are kept. The index is set as `frame_index`,
+/// and Python 3.11+ shows it in a note after the message too.
+fn frame_error(py: Python<'_>, err: PyErr, frame_index: usize, path: Option<&Path>) -> PyErr {
+    let value = err.value(py);
+    // exception instances have a __dict__, so this can't fail
+    let _ = value.setattr("frame_index", frame_index);
+    let note = match path {
+        Some(path) => format!("while adding frame {} ({})", frame_index, path.display()),
+        None => format!("while adding frame {}", frame_index),
+    };
+    if value.hasattr("add_note").unwrap_or(false) {
+        let _ = value.call_method1("add_note", (note,));
+    }
+    err
+}
+
+/// Borrows any object supporting the buffer protocol, such as bytes or memoryview
+fn pixel_buffer(pixels: &PyAny) -> PyResult<PyBuffer<u8>> {
+    let buffer = PyBuffer::<u8>::get(pixels)
+        .map_err(|_| exceptions::PyTypeError::new_err("pixels must be a bytes-like object, such as bytes, bytearray or memoryview"))?;
+    check_c_contiguous(&buffer, "pixels")?;
+    Ok(buffer)
+}
+
+/// Strided views would be read in the wrong order
+fn check_c_contiguous<T: pyo3::buffer::Element>(buffer: &PyBuffer<T>, name: &str) -> PyResult<()> {
+    if !buffer.is_c_contiguous() {
+        return Err(exceptions::PyValueError::new_err(format!(
+            "{} must be C-contiguous, but it's a transposed, sliced with a step, or Fortran-ordered view. \
+            Make a contiguous copy with np.ascontiguousarray() first", name)));
+    }
+    Ok(())
+}
+
+/// The buffer keeps the object borrowed until it's dropped
+unsafe fn buffer_as_slice<T: pyo3::buffer::Element>(buffer: &PyBuffer<T>) -> &[u8] {
+    slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes())
+}
+
+/// The GIF format stores sizes as 16-bit
+const MAX_DIMENSION: u32 = u16::MAX as u32;
+
+/// Size of a frame in bytes, which may not fit in `u32`
+fn frame_len(width: u32, height: u32, bytes_per_pixel: u32) -> Option<usize> {
+    (width as u64).checked_mul(height as u64)?.checked_mul(bytes_per_pixel as u64)?.try_into().ok()
+}
+
+/// The wrong variable passed as pixels deserves a clearer error than a size mismatch
+fn check_not_empty(pixels: &[u8]) -> PyResult<()> {
+    if pixels.is_empty() {
+        return Err(exceptions::PyValueError::new_err("empty pixel buffer"));
+    }
+    Ok(())
+}
+
+/// Pixel types that are made only of `u8`s, so any bytes are valid pixels at any alignment
+unsafe trait Pixel: Copy {}
+unsafe impl Pixel for RGBA8 {}
+unsafe impl Pixel for RGB8 {}
+unsafe impl Pixel for ARGB8 {}
+unsafe impl Pixel for BGRA8 {}
+
+/// Python buffers can start at any address, so the pixels can't be cast with `mem::transmute`
+fn cast_pixels<T: Pixel>(bytes: &[u8]) -> &[T] {
+    assert_eq!(1, mem::align_of::<T>());
+    assert_eq!(0, bytes.len() % mem::size_of::<T>());
+    unsafe { slice::from_raw_parts(bytes.as_ptr() as *const T, bytes.len() / mem::size_of::<T>()) }
+}
+
+/// Rectangle for add_frame_rgba_region()
+#[derive(Copy, Clone)]
+struct Region {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    full_width: u32,
+}
+
+impl Region {
+    /// Number of source pixels needed, up to the rectangle's last pixel
+    fn required_len(&self, width: u32, height: u32) -> Result<usize, &'static str> {
+        let Self { x, y, w, h, full_width } = *self;
+        if w == 0 || h == 0 {
+            return Err("the region must not be empty");
+        }
+        if x.checked_add(w).map_or(true, |right| right > width) || y.checked_add(h).map_or(true, |bottom| bottom > height) {
+            return Err("the region must be within the width and height of the gif");
+        }
+        if x + w > full_width {
+            return Err("the region must be within full_width");
+        }
+        Ok((y + h - 1) as usize * full_width as usize + (x + w) as usize)
+    }
+
+    /// `src` must have `required_len()` pixels
+    fn copy(&self, src: &[RGBA8], canvas: &mut [RGBA8], canvas_width: usize) {
+        let (x, w) = (self.x as usize, self.w as usize);
+        for row in self.y as usize..(self.y + self.h) as usize {
+            let src_start = row * self.full_width as usize + x;
+            let dst_start = row * canvas_width + x;
+            canvas[dst_start..dst_start + w].copy_from_slice(&src[src_start..src_start + w]);
+        }
+    }
+}
+
+/// Scales with bilinear interpolation
+fn resize_pixels(pixels: &[RGBA8], src_width: u32, src_height: u32, width: u32, height: u32) -> Result<Vec<RGBA8>, resize::Error> {
+    let mut resized = vec![RGBA8::new(0, 0, 0, 0); width as usize * height as usize];
+    resize::new(src_width as usize, src_height as usize, width as usize, height as usize, resize::Pixel::RGBA8P, resize::Type::Triangle)
+        .and_then(|mut resizer| resizer.resize(pixels, &mut resized))?;
+    Ok(resized)
+}
+
+/// Centers the frame on a canvas of the background color, after scaling it down if it's too large
+fn letterbox(pixels: &[RGBA8], src_width: u32, src_height: u32, width: u32, height: u32, background: RGBA8) -> Result<Vec<RGBA8>, resize::Error> {
+    let (w, h) = fit_size(src_width, src_height, width, height);
+    let scaled;
+    let pixels = if (w, h) == (src_width, src_height) { pixels } else {
+        scaled = resize_pixels(pixels, src_width, src_height, w, h)?;
+        &scaled
+    };
+    let mut canvas = vec![background; width as usize * height as usize];
+    let (x, y) = (((width - w) / 2) as usize, ((height - h) / 2) as usize);
+    for (row, src) in pixels.chunks_exact(w as usize).enumerate() {
+        let start = (y + row) * width as usize + x;
+        canvas[start..start + w as usize].copy_from_slice(src);
+    }
+    Ok(canvas)
+}
+
+/// Size within width and height with the aspect ratio of the source, which is kept if it fits
+fn fit_size(src_width: u32, src_height: u32, width: u32, height: u32) -> (u32, u32) {
+    if src_width <= width && src_height <= height {
+        return (src_width, src_height);
+    }
+    let scale = (width as f64 / src_width as f64).min(height as f64 / src_height as f64);
+    let scaled = |size: u32, max: u32| ((size as f64 * scale).round() as u32).clamp(1, max);
+    (scaled(src_width, width), scaled(src_height, height))
+}
+
+/// Fails with the position of the first index that isn't in the palette
+fn expand_indexed(indices: &[u8], palette: &[RGBA8]) -> Result<Vec<RGBA8>, usize> {
+    indices.iter().enumerate()
+        .map(|(i, &index)| palette.get(index as usize).copied().ok_or(i))
+        .collect()
+}
+
+fn bgra_to_rgba(pixels: &[BGRA8]) -> Vec<RGBA8> {
+    pixels.iter().map(|p| RGBA8::new(p.r, p.g, p.b, p.a)).collect()
+}
+
+/// Native-endian 16-bit samples, rounded to the nearest 8-bit value
+fn rgba16_to_rgba8(bytes: &[u8]) -> Vec<RGBA8> {
+    let sample = |b: &[u8]| ((u32::from(u16::from_ne_bytes([b[0], b[1]])) * 255 + 32767) / 65535) as u8;
+    bytes.chunks_exact(8).map(|px| {
+        RGBA8::new(sample(&px[0..2]), sample(&px[2..4]), sample(&px[4..6]), sample(&px[6..8]))
+    }).collect()
+}
+
+/// `frame_index` is the index of the frame being added, for error messages
+fn validate_timestamp(timestamp: f64, previous: Option<f64>, frame_index: u32) -> Result<(), String> {
+    if !timestamp.is_finite() || timestamp < 0.0 {
+        return Err(format!("timestamp must be a finite number >= 0, not {}", timestamp));
+    }
+    match previous {
+        // would be a zero-duration frame
+        Some(previous) if timestamp == previous => {
+            Err(format!("frame {} has the same timestamp {} as the previous frame", frame_index, timestamp))
+        },
+        Some(previous) if timestamp < previous => {
+            Err(format!("timestamp {} must be greater than the previous frame's timestamp {}", timestamp, previous))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Checks a frame from add_frame_rgba_at() against the frames around its index
+fn check_indexed_frame(frames: &BTreeMap<u32, f64>, index: u32, timestamp: f64) -> Result<(), String> {
+    if frames.contains_key(&index) {
+        return Err(format!("frame {} has already been added", index));
+    }
+    validate_timestamp(timestamp, frames.range(..index).next_back().map(|(_, &t)| t), index)?;
+    match frames.range(index..).next() {
+        Some((&next_index, &next)) if timestamp >= next => {
+            Err(format!("timestamp {} of frame {} must be less than the timestamp {} of frame {}", timestamp, index, next, next_index))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// finish() would write the gif nowhere
+fn check_output_set(has_output: bool) -> Result<(), &'static str> {
+    if !has_output {
+        return Err("no output configured; call set_file_output() or set_write_callback() first");
+    }
+    Ok(())
+}
+
+/// The first index that's not in the sorted indices
+fn missing_frame_index(indices: impl Iterator<Item = u32>) -> Option<u32> {
+    let mut expected = 0;
+    for index in indices {
+        if index != expected {
+            return Some(expected);
+        }
+        expected += 1;
+    }
+    None
+}
+
+/// The integer version of `validate_timestamp`
+fn validate_timestamp_ms(ms: u64, previous: Option<u64>, frame_index: u32) -> Result<(), String> {
+    match previous {
+        Some(previous) if ms <= previous => {
+            Err(format!("frame {} has timestamp {}ms, which must be greater than the previous frame's timestamp {}ms", frame_index, ms, previous))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// `dither` can be a bool or a strength
+fn dithering_level(dither: &PyAny) -> PyResult<f32> {
+    if let Ok(dither) = dither.downcast::<pyo3::types::PyBool>() {
+        return Ok(if dither.is_true() { 1. } else { 0. });
+    }
+    let level: f64 = dither.extract().map_err(|_| exceptions::PyTypeError::new_err("dither must be a bool or a float"))?;
+    if !(0. ..=1.).contains(&level) {
+        return Err(exceptions::PyValueError::new_err("dither must be between 0.0 and 1.0"));
+    }
+    Ok(level as f32)
+}
+
+/// Range of quality, motion_quality and lossy_quality, also in the Python module
+const MIN_QUALITY: u8 = 1;
+const MAX_QUALITY: u8 = 100;
+
+/// Python ints are wider than u8, so they're validated before narrowing
+fn quality_arg(quality: i64, name: &str) -> PyResult<u8> {
+    if !(i64::from(MIN_QUALITY)..=i64::from(MAX_QUALITY)).contains(&quality) {
+        return Err(exceptions::PyValueError::new_err(format!("{} must be between {} and {}, not {}", name, MIN_QUALITY, MAX_QUALITY, quality)));
+    }
+    Ok(quality as u8)
+}
+
+/// quality, and the motion_quality, lossy_quality and dithering level of a preset
+fn resolve_quality(quality: QualityArg) -> PyResult<(u8, Option<u8>, Option<u8>, Option<f32>)> {
+    Ok(match quality {
+        QualityArg::Preset(preset) => {
+            let (quality, motion_quality, lossy_quality, dithering_level) = preset.values();
+            (quality, Some(motion_quality), Some(lossy_quality), Some(dithering_level))
+        },
+        QualityArg::Int(quality) => (quality_arg(quality, "quality")?, None, None, None),
+    })
+}
+
+/// (r, g, b, a) with values from 0 to 255
+fn color_arg((r, g, b, a): (i64, i64, i64, i64)) -> PyResult<RGBA8> {
+    let c = |v: i64| u8::try_from(v).map_err(|_| exceptions::PyValueError::new_err("color values must be between 0 and 255"));
+    Ok(RGBA8::new(c(r)?, c(g)?, c(b)?, c(a)?))
+}
+
+/// True and False are the levels of the old boolean setting
+fn fast_level(fast: FastArg) -> PyResult<u8> {
+    match fast {
+        FastArg::Bool(true) => Ok(3),
+        FastArg::Bool(false) => Ok(1),
+        FastArg::Level(level) if (0..=3).contains(&level) => Ok(level as u8),
+        FastArg::Level(level) => Err(exceptions::PyValueError::new_err(format!("fast must be a level from 0 to 3, or a bool, not {}", level))),
+    }
+}
+
+/// Lossy compression strength as lossy_quality
+fn lossy_arg(lossy: i64) -> PyResult<u8> {
+    if !(0..=99).contains(&lossy) {
+        return Err(exceptions::PyValueError::new_err(format!("lossy must be between 0 and 99, not {}", lossy)));
+    }
+    Ok(MAX_QUALITY - lossy as u8)
+}
+
+fn check_max_pixels(width: u32, height: u32, max_pixels: Option<u64>) -> PyResult<()> {
+    match max_pixels {
+        Some(max_pixels) if u64::from(width) * u64::from(height) > max_pixels => {
+            Err(exceptions::PyValueError::new_err(format!("{}x{} is larger than max_pixels={}", width, height, max_pixels)))
+        },
+        _ => Ok(()),
+    }
+}
+
+fn max_frames_arg(max_frames: u32) -> PyResult<u32> {
+    if max_frames == 0 {
+        return Err(exceptions::PyValueError::new_err("max_frames must be at least 1"));
+    }
+    Ok(max_frames)
+}
+
+fn check_frame_limit(frame_count: u32, max_frames: Option<u32>) -> Result<(), String> {
+    match max_frames {
+        Some(max_frames) if frame_count >= max_frames => {
+            Err(format!("can't add more than max_frames={} frames", max_frames))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Python ints are checked before narrowing, so that they can't wrap around
+fn repeat_arg(repeat: i64) -> PyResult<i16> {
+    match i16::try_from(repeat) {
+        Ok(repeat) if repeat >= -1 => Ok(repeat),
+        _ => Err(exceptions::PyValueError::new_err(format!("repeat must be -1, 0, or from 1 to {}, not {}", i16::MAX, repeat))),
+    }
+}
+
+fn fps_arg(fps: f64) -> PyResult<f64> {
+    if !(fps > 0.0 && fps.is_finite()) {
+        return Err(exceptions::PyValueError::new_err("fps must be greater than 0"));
+    }
+    Ok(fps)
+}
+
+fn fit_arg(fit: &str, background: Option<RGBA8>) -> PyResult<Fit> {
+    match fit {
+        "error" => Ok(Fit::Error),
+        "stretch" => Ok(Fit::Stretch),
+        "pad" => Ok(Fit::Pad(background.unwrap_or(RGBA8::new(0, 0, 0, 0)))),
+        _ => Err(exceptions::PyValueError::new_err(format!("fit must be \"error\", \"stretch\" or \"pad\", not {:?}", fit))),
+    }
+}
+
+/// In the order of their values in `gifski_set_delay_rounding`
+const DELAY_ROUNDINGS: [&str; 3] = ["round", "floor", "nearest_even"];
+
+fn delay_rounding_arg(delay_rounding: &str) -> PyResult<u8> {
+    match DELAY_ROUNDINGS.iter().position(|&name| name == delay_rounding) {
+        Some(index) => Ok(index as u8),
+        None => Err(exceptions::PyValueError::new_err(format!("delay_rounding must be \"round\", \"floor\" or \"nearest_even\", not {:?}", delay_rounding))),
+    }
+}
+
+fn alpha_threshold_arg(alpha_threshold: i64) -> PyResult<u8> {
+    u8::try_from(alpha_threshold).map_err(|_| exceptions::PyValueError::new_err(format!("alpha_threshold must be between 0 and 255, not {}", alpha_threshold)))
+}
+
+fn speed_arg(speed: f64) -> PyResult<f64> {
+    if !(speed > 0.0 && speed.is_finite()) {
+        return Err(exceptions::PyValueError::new_err("speed must be greater than 0"));
+    }
+    Ok(speed)
+}
+
+/// Width and height from the IHDR chunk, which must be first in a PNG file
+#[cfg(feature = "png")]
+fn png_dimensions(header: &[u8; 24]) -> Result<(u32, u32), &'static str> {
+    if &header[..8] != b"\x89PNG\r\n\x1a\n" || &header[12..16] != b"IHDR" {
+        return Err("not a PNG file");
+    }
+    let width = u32::from_be_bytes(header[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(header[20..24].try_into().unwrap());
+    Ok((width, height))
+}
+
+#[cfg(feature = "png")]
+fn read_png_dimensions(path: &Path) -> PyResult<(u32, u32)> {
+    use std::io::Read;
+
+    let mut header = [0; 24];
+    std::fs::File::open(path)?.read_exact(&mut header)?;
+    png_dimensions(&header).map_err(|err| exceptions::PyValueError::new_err(format!("{}: {}", path.display(), err)))
+}
+
+/// gifski's C API takes paths as UTF-8 C strings
+fn path_to_cstring(path: &Path) -> Result<CString, String> {
+    let utf8_path = path.to_str().ok_or_else(|| format!("path {:?} is not valid UTF-8", path))?;
+    CString::new(utf8_path).map_err(|_| format!("path {:?} must not contain NUL bytes", utf8_path))
+}
+
+/// A GIF palette can't have more than 256 colors
+fn palette_arg(colors: Vec<(u8, u8, u8, u8)>) -> PyResult<Vec<RGBA8>> {
+    if colors.is_empty() || colors.len() > 256 {
+        return Err(exceptions::PyValueError::new_err("the palette must have between 1 and 256 colors"));
+    }
+    Ok(colors.into_iter().map(|(r, g, b, a)| RGBA8::new(r, g, b, a)).collect())
+}
+
+/// GIF comments are bytes, so only Latin-1 text can be stored as-is
+fn comment_bytes(text: &str) -> Result<CString, String> {
+    let bytes = text.chars().map(|c| match c {
+        '\0' => Err("the comment must not contain NUL characters".to_string()),
+        c if (c as u32) < 256 => Ok(c as u8),
+        c => Err(format!("the comment contains {:?}, which is not a Latin-1 character", c)),
+    }).collect::<Result<Vec<u8>, _>>()?;
+    if bytes.len() > 65535 {
+        return Err(format!("the comment must be at most 65535 characters long, not {}", bytes.len()));
+    }
+    Ok(CString::new(bytes).expect("no NUL"))
+}
+
+/// Binary search for the highest quality below `quality` for which `fits` is true,
+/// calling it at most `attempts` times. Fewer than 7 attempts may not find the highest.
+fn highest_fitting_quality<E>(quality: u8, attempts: u32, mut fits: impl FnMut(u8) -> Result<bool, E>) -> Result<Option<u8>, E> {
+    let (mut lowest, mut highest) = (1, quality.saturating_sub(1));
+    let mut found = None;
+    for _ in 0..attempts {
+        if lowest > highest {
+            break;
+        }
+        let quality = lowest + (highest - lowest + 1) / 2;
+        if fits(quality)? {
+            found = Some(quality);
+            lowest = quality + 1;
+        } else {
+            highest = quality - 1;
+        }
+    }
+    Ok(found)
+}
+
+/// Evenly spaced indices of at most `max_samples` out of `count` frames, for estimate_size()
+fn sample_indices(count: usize, max_samples: usize) -> Vec<usize> {
+    if count <= max_samples {
+        return (0..count).collect();
+    }
+    (0..max_samples).map(|i| i * count / max_samples).collect()
+}
+
+/// One palette for all frames, for preview_palette()
+fn combined_palette(frames: &[KeptFrame], width: u32, height: u32, quality: u8) -> Result<Vec<RGBA8>, imagequant::Error> {
+    let mut liq = imagequant::Attributes::new();
+    liq.set_quality(0, quality)?;
+    let mut histogram = imagequant::Histogram::new(&liq);
+    for frame in frames {
+        let mut image = liq.new_image_borrowed(&frame.pixels, width as usize, height as usize, 0.)?;
+        histogram.add_image(&liq, &mut image)?;
+    }
+    let mut res = histogram.quantize(&liq)?;
+    Ok(res.palette().to_vec())
+}
+
+/// Called by finish_async() on the event loop's thread
+#[pyfunction]
+fn resolve_future(future: &PyAny, result: PyObject, exception: PyObject) -> PyResult<()> {
+    // it may have been cancelled
+    if future.call_method0("done")?.is_true()? {
+        return Ok(());
+    }
+    if exception.is_none(future.py()) {
+        future.call_method1("set_result", (result,))?;
+    } else {
+        future.call_method1("set_exception", (exception,))?;
+    }
+    Ok(())
+}
+
+/// version()
+///
+/// Version of gifski, e.g. "1.7.1".
+///
+/// The encoder is compiled into this module, so this is also the version of
+/// the native library.
+#[pyfunction]
+#[pyo3(text_signature = "()")]
+fn version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+/// build_info()
+///
+/// How this module was built, for bug reports.
+///
+/// Returns a dict with keys:
+///     version: same as version()
+///     library: always "vendored", because the encoder is compiled into this
+///         module, and no system libgifski is loaded
+///     api: always "c", because the module calls gifski's C API functions
+///         (the same as in gifski.h) in-process
+///     features: list of the Cargo features the module was compiled with
+///     target: target triple, e.g. "x86_64-unknown-linux-gnu"
+///     profile: "release" or "debug"
+#[pyfunction]
+#[pyo3(text_signature = "()")]
+fn build_info(py: Python<'_>) -> PyResult<&pyo3::types::PyDict> {
+    let features = [
+        ("png", cfg!(feature = "png")),
+        ("gifsicle", cfg!(feature = "gifsicle")),
+        ("video", cfg!(feature = "video")),
+        ("natord", cfg!(feature = "natord")),
+    ];
+    let dict = pyo3::types::PyDict::new(py);
+    dict.set_item("version", version())?;
+    dict.set_item("library", "vendored")?;
+    dict.set_item("api", "c")?;
+    dict.set_item("features", features.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect::<Vec<_>>())?;
+    dict.set_item("target", env!("GIFSKI_BUILD_TARGET"))?;
+    dict.set_item("profile", env!("GIFSKI_BUILD_PROFILE"))?;
+    Ok(dict)
+}
+
+/// set_num_threads(num_threads, /)
+///
+/// Limit the number of threads used for quantization of frames.
+///
+/// The thread pool is shared by all Gifski objects in the process, so this
+/// has to be called once, before encoding anything. Calling it again with the
+/// same number does nothing. By default there's one thread per CPU core.
+/// Gifski also uses a few threads of its own for the other stages of encoding.
+///
+/// Parameters
+/// ----------
+/// num_threads : int
+///     Positive number of threads.
+#[pyfunction]
+#[pyo3(text_signature = "(num_threads, /)")]
+fn set_num_threads(num_threads: usize) -> PyResult<()> {
+    init_thread_pool(num_threads_arg(num_threads)?)
+}
+
+fn num_threads_arg(num_threads: usize) -> PyResult<usize> {
+    if num_threads == 0 {
+        return Err(exceptions::PyValueError::new_err("num_threads must be at least 1"));
+    }
+    Ok(num_threads)
+}
+
+/// Succeeds if the pool has already been started with the same number of threads
+fn init_thread_pool(num_threads: usize) -> PyResult<()> {
+    let res = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build_global();
+    if res.is_err() && rayon::current_num_threads() != num_threads {
+        return Err(exceptions::PyRuntimeError::new_err("the thread pool has already been started, set_num_threads() must be called before encoding"));
+    }
+    Ok(())
+}
+
+/// encode_directory(input_dir, output, fps, /, quality=90, pattern="*.png")
+///
+/// Make a gif file from all PNG files in a directory, in one call.
+///
+/// Files matching the pattern are sorted in natural order (frame2.png
+/// before frame10.png), and encoded like with Gifski.from_png_files().
+/// Returns the same FinishStats as Gifski.finish().
+///
+/// Parameters
+/// ----------
+/// input_dir : str or os.PathLike
+///     Directory with the frames.
+/// output : str or os.PathLike
+///     File path for writing the output gif.
+/// fps : float
+///     Frames per second.
+/// quality : int
+///     integer from 1 (best compression) to 100 (best quality)
+/// pattern : str
+///     Shell-style wildcard for names of the files, see fnmatch.
+#[cfg(all(feature = "png", feature = "natord"))]
+#[pyfunction(quality = "90", pattern = "\"*.png\"")]
+#[pyo3(text_signature = "(input_dir, output, fps, /, quality=90, pattern=\"*.png\")")]
+fn encode_directory(py: Python<'_>, input_dir: PathBuf, output: PathBuf, fps: f64, quality: i64, pattern: &str) -> PyResult<PyFinishStats> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(&input_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            if let Ok(name) = entry.file_name().into_string() {
+                names.push(name);
+            }
+        }
+    }
+    let mut names: Vec<String> = py.import("fnmatch")?.call_method1("filter", (names, pattern))?.extract()?;
+    if names.is_empty() {
+        return Err(exceptions::PyFileNotFoundError::new_err(format!("no files matching {} in {}", pattern, input_dir.display())));
+    }
+    names.sort_by(|a, b| natord::compare(a, b));
+
+    let paths = names.into_iter().map(|name| input_dir.join(name)).collect();
+    unsafe { PyGifski::encode_png_files(py, paths, fps, output, quality) }
+}
+
+/// encode(frames, output, fps, /, width=None, height=None, **settings)
+///
+/// Make a gif file from an iterable of frames, in one call.
+///
+/// The frames are added like with Gifski.add_frame_rgba(), at timestamps
+/// computed from fps. The width and height are taken from the first frame,
+/// if it's an array with shape (height, width, 4), such as a NumPy array.
+/// For frames in bytes, they have to be given. Every frame must have the same
+/// size. An existing output file is overwritten, and if a frame is invalid,
+/// the unfinished output is removed. Returns the same FinishStats as
+/// Gifski.finish().
+///
+/// The frames are taken from the iterable one at a time, and each frame is
+/// released once it has been added, so a generator can make arbitrarily long
+/// animations without keeping all the frames in memory.
+///
+/// Example:
+///     gifski.encode((np.asarray(img.convert('RGBA')) for img in imgs), "output/path.gif", 24, quality=80)
+///
+/// Parameters
+/// ----------
+/// frames : iterable
+///     RGBA pixel data (bytes-like objects or uint8 arrays).
+/// output : str or os.PathLike
+///     File path for writing the output gif.
+/// fps : float
+///     Frames per second.
+/// width, height : int, optional
+///     Size of the frames. Required if the frames don't have a shape.
+/// **settings
+///     Other arguments of Gifski(), such as quality, fast or repeat.
+#[pyfunction(width = "None", height = "None", settings = "**")]
+#[pyo3(text_signature = "(frames, output, fps, /, width=None, height=None, **settings)")]
+fn encode(py: Python<'_>, frames: &PyAny, output: PathBuf, fps: f64, width: Option<u32>, height: Option<u32>, settings: Option<&pyo3::types::PyDict>) -> PyResult<PyFinishStats> {
+    let fps = fps_arg(fps)?;
+    let mut frames = frames.iter()?;
+    // objects are normally released when the function returns, so each frame gets its own pool.
+    // Nothing from the pools outlives them.
+    let (g, size) = unsafe {
+        let pool = py.new_pool();
+        let py = pool.python();
+        let first = frames.next().ok_or_else(|| exceptions::PyValueError::new_err("frames must not be empty"))??;
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            (None, None) => PyBuffer::<u8>::get(first).ok()
+                .and_then(|buffer| shape_dimensions(buffer.shape()))
+                .ok_or_else(|| exceptions::PyValueError::new_err("width and height are required when the first frame isn't an array with shape (height, width, 4)"))?,
+            _ => return Err(exceptions::PyValueError::new_err("width and height must be given together")),
+        };
+
+        let g: &PyCell<PyGifski> = py.get_type::<PyGifski>().call((width, height), settings)?.downcast()?;
+        g.borrow_mut().set_file_output(output, true)?;
+        encode_frame(&mut g.borrow_mut(), py, 0, Ok(first), 0., (width, height))?;
+        (Py::<PyGifski>::from(g), (width, height))
+    };
+    let mut g = g.as_ref(py).borrow_mut();
+    for index in 1.. {
+        unsafe {
+            let pool = py.new_pool();
+            let py = pool.python();
+            let frame = match frames.next() {
+                Some(frame) => frame,
+                None => break,
+            };
+            encode_frame(&mut g, py, index, frame, index as f64 / fps, size)?;
+        }
+    }
+    unsafe { g.finish(py, None, false) }
+}
+
+/// Adds a frame for encode(), or aborts with the frame's index in the error
+unsafe fn encode_frame(g: &mut PyGifski, py: Python<'_>, index: usize, frame: PyResult<&PyAny>, timestamp: f64, (width, height): (u32, u32)) -> PyResult<()> {
+    let res = frame.and_then(|frame| {
+        if let Ok(buffer) = PyBuffer::<u8>::get(frame) {
+            if buffer.dimensions() > 1 && shape_dimensions(buffer.shape()) != Some((width, height)) {
+                return Err(exceptions::PyValueError::new_err(format!("shape {:?} doesn't match the size {}x{} of the first frame", buffer.shape(), width, height)));
+            }
+        }
+        g.add_frame_rgba(py, frame, timestamp)
+    });
+    if let Err(err) = res {
+        g.abort(py);
+        return Err(frame_error(py, err, index, None));
+    }
+    Ok(())
+}
+
+/// Seconds from a GIF frame's delay in hundredths of a second, with browsers' minimum
+fn gif_delay(delay: u16) -> f64 {
+    f64::from(if delay <= 1 { 10 } else { delay }) / 100.
+}
+
+/// Width and height of an array with shape (height, width, 4)
+fn shape_dimensions(shape: &[usize]) -> Option<(u32, u32)> {
+    match *shape {
+        [height, width, 4] => Some((width.try_into().ok()?, height.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+#[pymodule]
+fn gifski(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyGifski>()?;
+    m.add_class::<PyGifskiPreset>()?;
+    m.add_class::<PyGifskiBuilder>()?;
+    m.add("__version__", version())?;
+    m.add("MIN_QUALITY", MIN_QUALITY)?;
+    m.add("MAX_QUALITY", MAX_QUALITY)?;
+    m.add_function(wrap_pyfunction!(version, m)?)?;
+    m.add_function(wrap_pyfunction!(build_info, m)?)?;
+    m.add_function(wrap_pyfunction!(set_num_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(encode, m)?)?;
+    #[cfg(all(feature = "png", feature = "natord"))]
+    m.add_function(wrap_pyfunction!(encode_directory, m)?)?;
+    m.add_class::<PyFinishStats>()?;
+    m.add_class::<PyEncoderWarning>()?;
+    m.add_class::<PyFinishIter>()?;
+    m.add_class::<PyFinishChunks>()?;
+    add_exceptions(py, m)?;
+    Ok(())
+}
+
+#[test]
+fn quality_range() {
+    for quality in [0, 101, 256, 356, -1] {
+        assert!(quality_arg(quality, "quality").is_err(), "{}", quality);
+    }
+    assert_eq!(Some(MIN_QUALITY), quality_arg(1, "quality").ok());
+    assert_eq!(Some(MAX_QUALITY), quality_arg(100, "quality").ok());
+    assert!(quality_arg(0, "motion_quality").is_err());
+    run_python(r#"
+        err = raises(ValueError, gifski.Gifski, 2, 2, quality=1000)
+        assert str(err) == "quality must be between 1 and 100, not 1000", err
+    "#);
+}
+
+#[test]
+fn nul_in_path() {
+    assert!(path_to_cstring(Path::new("out\0.gif")).is_err());
+    assert_eq!(b"out.gif", path_to_cstring(Path::new("out.gif")).unwrap().as_bytes());
+}
+
+#[test]
+#[cfg(feature = "png")]
+fn png_header() {
+    let mut header = *b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\x01\x40\0\0\0\xf0";
+    assert_eq!(Ok((320, 240)), png_dimensions(&header));
+    header[1] = b'J';
+    assert!(png_dimensions(&header).is_err());
+}
+
+#[test]
+fn unaligned_pixels() {
+    let bytes = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+    let pixels: &[RGBA8] = cast_pixels(&bytes[1..]);
+    assert_eq!(&[RGBA8::new(1, 2, 3, 4), RGBA8::new(5, 6, 7, 8)], pixels);
+    let pixels: &[RGB8] = cast_pixels(&bytes[3..]);
+    assert_eq!(&[RGB8::new(3, 4, 5), RGB8::new(6, 7, 8)], pixels);
+    let pixels: &[ARGB8] = cast_pixels(&bytes[5..]);
+    assert_eq!((5, 6, 7, 8), (pixels[0].a, pixels[0].r, pixels[0].g, pixels[0].b));
+}
+
+#[test]
+fn frame_len_overflow() {
+    assert_eq!(None, frame_len(u32::MAX, u32::MAX, 4));
+    assert_ne!(Some(40000u32.wrapping_mul(40000).wrapping_mul(4) as usize), frame_len(40000, 40000, 4));
+    assert_eq!(Some(12), frame_len(2, 2, 3));
+}
+
+#[test]
+fn shared_state_is_thread_safe() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    // these are given to other threads as raw pointers, which the compiler doesn't check
+    assert_send_sync::<GifskiHandleInternal>();
+    assert_send_sync::<Callbacks>();
+}
+
+#[test]
+fn quality_search() {
+    let mut tried = Vec::new();
+    let found = highest_fitting_quality::<()>(100, 7, |q| { tried.push(q); Ok(q <= 37) }).unwrap();
+    assert_eq!(Some(37), found);
+    assert!(tried.len() <= 7);
+    assert_eq!(Ok(None), highest_fitting_quality::<()>(100, 7, |_| Ok(false)));
+    assert_eq!(Ok(Some(99)), highest_fitting_quality::<()>(100, 7, |_| Ok(true)));
+    assert_eq!(Ok(None), highest_fitting_quality::<()>(1, 7, |_| panic!("nothing below 1")));
+    assert_eq!(Err(()), highest_fitting_quality(100, 7, |_| Err(())));
+}
+
+#[test]
+fn combined_palette_colors() {
+    let red = RGBA8::new(255, 0, 0, 255);
+    let blue = RGBA8::new(0, 0, 255, 255);
+    let frames = [KeptFrame { pixels: vec![red; 4], timestamp: 0. }, KeptFrame { pixels: vec![blue; 4], timestamp: 1. }];
+    let palette = combined_palette(&frames, 2, 2, 100).unwrap();
+    assert!(palette.contains(&red));
+    assert!(palette.contains(&blue));
+}
+
+#[test]
+fn empty_pixels() {
+    assert!(check_not_empty(&[]).is_err());
+    assert!(check_not_empty(&[0; 4]).is_ok());
+}
+
+#[test]
+fn timestamps() {
+    assert!(validate_timestamp(0., None, 0).is_ok());
+    assert!(validate_timestamp(0.5, Some(0.), 1).is_ok());
+    assert!(validate_timestamp(0., Some(0.), 1).is_err());
+    assert!(validate_timestamp(0.5, Some(0.5), 2).unwrap_err().contains("frame 2"));
+    assert!(validate_timestamp(0.4, Some(0.5), 2).is_err());
+    for timestamp in [f64::NAN, f64::INFINITY, -1.] {
+        assert!(validate_timestamp(timestamp, None, 0).is_err(), "{}", timestamp);
+    }
+}
+
+/// Defined for the Python code given to `run_python`
+#[cfg(test)]
+const PY_TEST_HELPERS: &str = r#"
+import io
+
+class FailingWriter:
+    def write(self, data):
+        raise ValueError("disk full")
+
+def add_frames(g, count=3):
+    for i in range(count):
+        g.add_frame_rgba(bytes([i * 255 // count, 0, 0, 255]) * (g.width * g.height), i / 10)
+
+def is_gif(data):
+    return data.startswith(b"GIF89a") and data.endswith(b";")
+
+# in centiseconds, from the graphic control extensions
+def delays(gif):
+    return [int.from_bytes(gif[i + 4:i + 6], "little") for i in range(len(gif) - 5) if gif[i:i + 3] == b"\x21\xf9\x04"]
+
+def raises(exception, call, *args, **kwargs):
+    try:
+        call(*args, **kwargs)
+    except exception as err:
+        return err
+    raise AssertionError(f"{call.__name__}() hasn't raised {exception.__name__}")
+"#;
+
+/// Runs (indented) Python code with this module as `gifski`, and fails if the code raises
+#[cfg(test)]
+fn run_python(code: &str) {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let res = (|| -> PyResult<()> {
+            let module = PyModule::new(py, "gifski")?;
+            gifski(py, module)?;
+            let globals = pyo3::types::PyDict::new(py);
+            globals.set_item("__builtins__", py.import("builtins")?)?;
+            globals.set_item("gifski", module)?;
+            py.run(PY_TEST_HELPERS, Some(globals), None)?;
+            let code: String = py.import("textwrap")?.call_method1("dedent", (code,))?.extract()?;
+            py.run(&code, Some(globals), None)
+        })();
+        if let Err(err) = res {
+            err.print(py);
+            panic!("the Python code has raised an exception");
+        }
+    });
+}
+
+#[test]
+fn py_finish_iter() {
+    run_python(r#"
+        out = io.BytesIO()
+        g = gifski.Gifski(2, 2)
+        g.set_write_callback(out)
+        add_frames(g, 5)
+        progress = list(g.finish_iter())
+        assert progress == sorted(progress) and progress[-1] == 1.0, progress
+        assert is_gif(out.getvalue())
+
+        g = gifski.Gifski(2, 2)
+        g.set_write_callback(FailingWriter())
+        add_frames(g, 1)
+        assert str(raises(ValueError, list, g.finish_iter())) == "disk full"
+    "#);
+}
+
+#[test]
+fn py_finish_async() {
+    run_python(r#"
+        import asyncio
+
+        async def finish(g):
+            return await g.finish_async()
+
+        out = io.BytesIO()
+        g = gifski.Gifski(2, 2)
+        g.set_write_callback(out)
+        add_frames(g)
+        stats = asyncio.run(finish(g))
+        assert stats.frames_written == 3 and stats.output_bytes == len(out.getvalue())
+        assert is_gif(out.getvalue())
+
+        g = gifski.Gifski(2, 2)
+        g.set_write_callback(FailingWriter())
+        add_frames(g, 1)
+        assert str(raises(ValueError, asyncio.run, finish(g))) == "disk full"
+
+        # needs a running event loop
+        g = gifski.Gifski(2, 2)
+        g.set_write_callback(io.BytesIO())
+        raises(RuntimeError, g.finish_async)
+    "#);
+}
+
+#[test]
+fn timestamps_ms() {
+    assert!(validate_timestamp_ms(0, None, 0).is_ok());
+    assert!(validate_timestamp_ms(33, Some(0), 1).is_ok());
+    assert!(validate_timestamp_ms(33, Some(33), 2).unwrap_err().contains("frame 2"));
+    assert!(validate_timestamp_ms(32, Some(33), 2).is_err());
+}
+
+#[test]
+fn comments() {
+    assert_eq!(comment_bytes("gifski 1.7").unwrap().as_bytes(), b"gifski 1.7");
+    assert_eq!(comment_bytes("caf\u{e9}").unwrap().as_bytes(), b"caf\xE9");
+    assert!(comment_bytes("\u{2603}").is_err());
+    assert!(comment_bytes("a\0b").is_err());
+    assert!(comment_bytes(&"x".repeat(65535)).is_ok());
+    assert!(comment_bytes(&"x".repeat(65536)).is_err());
+}
+
+#[test]
+fn presets_are_valid() {
+    for preset in [PyGifskiPreset::LOW, PyGifskiPreset::BALANCED, PyGifskiPreset::HIGH, PyGifskiPreset::MAX] {
+        let (quality, motion_quality, lossy_quality, dithering_level) = preset.values();
+        for q in [quality, motion_quality, lossy_quality] {
+            assert!(quality_arg(q.into(), "quality").is_ok(), "{:?}", preset);
+        }
+        assert!((0. ..=1.).contains(&dithering_level), "{:?}", preset);
+    }
+}
+
+#[test]
+fn bgra_order() {
+    let bytes = [1, 2, 3, 4, 5, 6, 7, 8];
+    assert_eq!(bgra_to_rgba(cast_pixels(&bytes)), [RGBA8::new(3, 2, 1, 4), RGBA8::new(7, 6, 5, 8)]);
+}
+
+#[test]
+fn rgba16_rounding() {
+    let bytes: Vec<u8> = [0u16, 128, 129, 65535, 257 * 100, 257 * 100 + 128, 257 * 100 + 129, 65406]
+        .iter().flat_map(|v| v.to_ne_bytes()).collect();
+    assert_eq!(rgba16_to_rgba8(&bytes), [RGBA8::new(0, 0, 1, 255), RGBA8::new(100, 100, 101, 254)]);
+}
+
+#[test]
+fn indexed_pixels() {
+    let palette = [RGBA8::new(1, 2, 3, 4), RGBA8::new(5, 6, 7, 8)];
+    assert_eq!(expand_indexed(&[1, 0, 1], &palette), Ok(vec![palette[1], palette[0], palette[1]]));
+    assert_eq!(expand_indexed(&[0, 1, 2, 0], &palette), Err(2));
+}
+
+#[test]
+fn regions() {
+    let region = Region { x: 1, y: 1, w: 2, h: 1, full_width: 4 };
+    assert_eq!(region.required_len(3, 2), Ok(7));
+    assert!(region.required_len(2, 2).is_err());
+    assert!(Region { full_width: 2, ..region }.required_len(3, 2).is_err());
+    assert!(Region { h: 0, ..region }.required_len(3, 2).is_err());
+    assert!(Region { y: u32::MAX, ..region }.required_len(3, 2).is_err());
+
+    let src: Vec<_> = (0..7).map(|i| RGBA8::new(i, 0, 0, 255)).collect();
+    let mut canvas = vec![RGBA8::default(); 6];
+    region.copy(&src, &mut canvas, 3);
+    assert_eq!(canvas.iter().map(|px| px.a).collect::<Vec<_>>(), [0, 0, 0, 0, 255, 255]);
+    assert_eq!((canvas[4].r, canvas[5].r), (5, 6));
+}
+
+#[test]
+fn py_finish_chunks() {
+    run_python(r#"
+        g = gifski.Gifski(2, 2)
+        add_frames(g)
+        chunks = list(g.finish_chunks())
+        assert chunks and all(isinstance(chunk, bytes) and chunk for chunk in chunks)
+        assert is_gif(b"".join(chunks))
+        raises(RuntimeError, g.finish_chunks)
+
+        # the chunks are the output
+        g = gifski.Gifski(2, 2)
+        g.set_write_callback(io.BytesIO())
+        raises(gifski.GifskiInvalidState, g.finish_chunks)
+    "#);
+}
+
+#[test]
+fn max_pixels() {
+    assert!(check_max_pixels(u32::MAX, u32::MAX, None).is_ok());
+    assert!(check_max_pixels(100, 100, Some(10_000)).is_ok());
+    assert!(check_max_pixels(100, 101, Some(10_000)).is_err());
+}
+
+#[test]
+fn py_strided_buffers() {
+    run_python(r#"
+        g = gifski.Gifski(2, 2)
+        err = raises(ValueError, g.add_frame_rgba, memoryview(bytes(32))[::2], 0.0)
+        assert "np.ascontiguousarray" in str(err), err
+        # shape (2, 2, 4), but every other row of a (4, 2, 4) array
+        err = raises(ValueError, g.add_frame_ndarray, memoryview(bytes(32)).cast("B", (4, 2, 4))[::2], 0.0)
+        assert "np.ascontiguousarray" in str(err), err
+    "#);
+}
+
+#[test]
+fn py_finish_timeout() {
+    run_python(r#"
+        import time
+
+        class SlowWriter:
+            def write(self, data):
+                time.sleep(0.2)
+
+        g = gifski.Gifski(2, 2)
+        g.set_write_callback(SlowWriter())
+        add_frames(g, 5)
+        raises(TimeoutError, g.finish, timeout=0.01)
+        # the timeout has aborted the encoding
+        raises(RuntimeError, g.finish)
+
+        out = io.BytesIO()
+        g = gifski.Gifski(2, 2)
+        g.set_write_callback(out)
+        add_frames(g, 1)
+        raises(ValueError, g.finish, timeout=-1)
+        g.finish(timeout=60)
+        assert is_gif(out.getvalue())
+    "#);
+}
+
+#[test]
+fn py_tee_writers() {
+    run_python(r#"
+        import os
+        import tempfile
+
+        first, second, output = io.BytesIO(), io.BytesIO(), io.BytesIO()
+        g = gifski.Gifski(2, 2)
+        g.add_write_callback(first)
+        g.add_write_callback(second)
+        g.set_write_callback(output)
+        raises(gifski.GifskiInvalidState, g.add_write_callback, io.BytesIO())
+        add_frames(g)
+        g.finish()
+        assert is_gif(output.getvalue())
+        assert first.getvalue() == second.getvalue() == output.getvalue()
+
+        with tempfile.TemporaryDirectory() as dir:
+            path = os.path.join(dir, "out.gif")
+            copy = io.BytesIO()
+            g = gifski.Gifski(2, 2)
+            g.add_write_callback(copy)
+            g.set_file_output(path)
+            add_frames(g)
+            g.finish()
+            with open(path, "rb") as f:
+                assert f.read() == copy.getvalue()
+    "#);
+}
+
+#[test]
+fn fit_sizes() {
+    assert_eq!(fit_size(10, 10, 20, 30), (10, 10));
+    assert_eq!(fit_size(40, 20, 20, 20), (20, 10));
+    assert_eq!(fit_size(20, 40, 20, 20), (10, 20));
+    assert_eq!(fit_size(1000, 1, 10, 10), (10, 1));
+}
+
+#[test]
+fn letterboxed() {
+    let red = RGBA8::new(255, 0, 0, 255);
+    let bg = RGBA8::new(0, 0, 0, 0);
+    let canvas = letterbox(&[red], 1, 1, 3, 2, bg).unwrap();
+    assert_eq!(canvas, [bg, red, bg, bg, bg, bg]);
+}
+
+#[test]
+fn speed_range() {
+    assert_eq!(speed_arg(2.).unwrap(), 2.);
+    for speed in [0., -1., f64::NAN, f64::INFINITY] {
+        assert!(speed_arg(speed).is_err(), "{}", speed);
+    }
+}
+
+#[test]
+fn gif_delays() {
+    assert_eq!(gif_delay(0), 0.1);
+    assert_eq!(gif_delay(1), 0.1);
+    assert_eq!(gif_delay(2), 0.02);
+    assert_eq!(gif_delay(150), 1.5);
+}
+
+#[test]
+fn array_shapes() {
+    assert_eq!(shape_dimensions(&[480, 640, 4]), Some((640, 480)));
+    assert_eq!(shape_dimensions(&[480, 640, 3]), None);
+    assert_eq!(shape_dimensions(&[480 * 640 * 4]), None);
+}
+
+#[test]
+fn fast_levels() {
+    assert_eq!(fast_level(FastArg::Bool(true)).unwrap(), 3);
+    assert_eq!(fast_level(FastArg::Bool(false)).unwrap(), 1);
+    assert_eq!(fast_level(FastArg::Level(0)).unwrap(), 0);
+    assert!(fast_level(FastArg::Level(4)).is_err());
+    assert!(fast_level(FastArg::Level(-1)).is_err());
+}
+
+#[test]
+fn lossy_strength() {
+    assert_eq!(lossy_arg(0).unwrap(), 100);
+    assert_eq!(lossy_arg(99).unwrap(), 1);
+    assert!(lossy_arg(100).is_err());
+    assert!(lossy_arg(-1).is_err());
+}
+
+#[test]
+fn frame_limit() {
+    assert!(check_frame_limit(u32::MAX, None).is_ok());
+    assert!(check_frame_limit(9, Some(10)).is_ok());
+    assert!(check_frame_limit(10, Some(10)).unwrap_err().contains("max_frames=10"));
+}
+
+#[test]
+fn output_required() {
+    assert!(check_output_set(true).is_ok());
+    assert!(check_output_set(false).unwrap_err().contains("set_file_output()"));
+}
+
+#[test]
+fn indexed_frames() {
+    let mut frames = BTreeMap::new();
+    assert!(check_indexed_frame(&frames, 2, 0.2).is_ok());
+    frames.insert(2, 0.2);
+    assert!(check_indexed_frame(&frames, 2, 0.3).unwrap_err().contains("already"));
+    assert!(check_indexed_frame(&frames, 0, 0.).is_ok());
+    assert!(check_indexed_frame(&frames, 1, 0.2).is_err());
+    assert!(check_indexed_frame(&frames, 3, 0.2).is_err());
+    assert!(check_indexed_frame(&frames, 3, 0.3).is_ok());
+    assert_eq!(missing_frame_index(frames.keys().copied()), Some(0));
+    frames.insert(0, 0.);
+    assert_eq!(missing_frame_index(frames.keys().copied()), Some(1));
+    frames.insert(1, 0.1);
+    assert_eq!(missing_frame_index(frames.keys().copied()), None);
+    assert_eq!(missing_frame_index(std::iter::empty()), None);
+}
+
+#[test]
+fn duplicate_frames() {
+    let a = [RGBA8::new(1, 2, 3, 255); 4];
+    let mut b = a;
+    assert!(!is_duplicate_frame(&[], &a));
+    assert!(is_duplicate_frame(&a, &b));
+    b[3].a = 254;
+    assert!(!is_duplicate_frame(&a, &b));
+}
+
+#[test]
+fn py_dedupe() {
+    run_python(r#"
+        red = bytes([255, 0, 0, 255]) * 4
+        blue = bytes([0, 0, 255, 255]) * 4
+        out = io.BytesIO()
+        g = gifski.Gifski(2, 2, dedupe=True)
+        g.set_write_callback(out)
+        for i, pixels in enumerate([red, red, red, blue]):
+            g.add_frame_rgba(pixels, i / 10)
+        assert g.frame_count == 4
+        stats = g.finish()
+        assert stats.frames_merged == 2 and stats.frames_written == 2
+        written = delays(out.getvalue())
+        assert len(written) == 2 and written[0] == 30, written
+    "#);
+}
+
+#[test]
+fn repeat_range() {
+    assert_eq!(repeat_arg(-1).unwrap(), -1);
+    assert_eq!(repeat_arg(0).unwrap(), 0);
+    assert_eq!(repeat_arg(32767).unwrap(), 32767);
+    assert!(repeat_arg(32768).is_err());
+    assert!(repeat_arg(40000).is_err());
+    assert!(repeat_arg(-2).is_err());
+}
+
+#[test]
+fn py_finish_thumbnail() {
+    run_python(r#"
+        import os
+        import tempfile
+
+        with tempfile.TemporaryDirectory() as dir:
+            path = os.path.join(dir, "thumbnail.gif")
+            g = gifski.Gifski(2, 2, keep_frames=True)
+            g.set_file_output(path)
+            add_frames(g)
+            raises(IndexError, g.finish_thumbnail, 3)
+            stats = g.finish_thumbnail(1)
+            assert stats.frames_written == 1 and stats.output_bytes == os.path.getsize(path)
+            with open(path, "rb") as f:
+                assert is_gif(f.read())
+
+        # the writer can't be rewritten
+        g = gifski.Gifski(2, 2, keep_frames=True)
+        g.set_write_callback(io.BytesIO())
+        add_frames(g, 1)
+        raises(RuntimeError, g.finish_thumbnail)
+    "#);
+}
+
+#[test]
+fn py_add_frames_from_threads() {
+    run_python(r#"
+        import threading
+
+        g = gifski.Gifski(2, 2, fps=10)
+        g.set_write_callback(io.BytesIO())
+        start = threading.Barrier(2)
+        added = [0, 0]
+        conflicts = []
+
+        def add_from_thread(n):
+            start.wait()
+            while added[n] < 20:
+                try:
+                    g.add_frame(bytes(16))
+                    added[n] += 1
+                except RuntimeError as err:
+                    # the other thread is in the middle of a call
+                    conflicts.append(err)
+
+        threads = [threading.Thread(target=add_from_thread, args=(n,)) for n in range(2)]
+        for t in threads:
+            t.start()
+        for t in threads:
+            t.join()
+        assert added == [20, 20] and g.frame_count == 40, (added, g.frame_count, conflicts)
+        g.finish()
+    "#);
+}
+
+#[test]
+fn py_finish_to_size() {
+    run_python(r#"
+        import random
+
+        rng = random.Random(1)
+        frames = [bytes(rng.randrange(256) for _ in range(16 * 16 * 4)) for _ in range(3)]
+        warnings = []
+
+        def encoder():
+            g = gifski.Gifski(16, 16, keep_frames=True)
+            g.set_warning_callback(lambda warning: warnings.append(warning.category))
+            for i, pixels in enumerate(frames):
+                g.add_frame_rgba(pixels, i / 10)
+            return g
+
+        assert is_gif(encoder().finish_to_size(10_000_000))
+        assert warnings == []
+
+        # can't fit, so the smallest is returned
+        assert is_gif(encoder().finish_to_size(100, max_attempts=3))
+        assert warnings == ["size_exceeded"]
+
+        g = gifski.Gifski(16, 16)
+        g.add_frame_rgba(frames[0], 0.0)
+        raises(RuntimeError, g.finish_to_size, 100)
+    "#);
+}
+
+#[test]
+fn late_output() {
+    use std::io::Read;
+
+    let callbacks = Callbacks::default();
+    assert_eq!(callbacks.replace_memory_output(Output::Memory(Vec::new())), GifskiError::INVALID_STATE);
+    *callbacks.output.lock().unwrap() = Some(Output::Memory(b"GIF89a".to_vec()));
+    assert_eq!(write_output(&callbacks, b"frame"), GifskiError::OK);
+
+    let path = std::env::temp_dir().join(format!("gifski-py-late-output-{}.gif", std::process::id()));
+    let file = std::fs::File::create(&path).unwrap();
+    assert_eq!(callbacks.replace_memory_output(Output::File(file)), GifskiError::OK);
+    assert_eq!(write_output(&callbacks, b";"), GifskiError::OK);
+    assert_eq!(write_output(&callbacks, b""), GifskiError::OK);
+    // already replaced
+    assert_eq!(callbacks.replace_memory_output(Output::Memory(Vec::new())), GifskiError::INVALID_STATE);
+    *callbacks.output.lock().unwrap() = None;
+
+    let mut written = Vec::new();
+    std::fs::File::open(&path).unwrap().read_to_end(&mut written).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(written, b"GIF89aframe;");
+}
+
+#[test]
+fn py_output_before_or_after_frames() {
+    run_python(r#"
+        import os
+        import tempfile
+
+        early = io.BytesIO()
+        g = gifski.Gifski(2, 2)
+        g.set_write_callback(early)
+        add_frames(g)
+        early_stats = g.finish()
+
+        late = io.BytesIO()
+        g = gifski.Gifski(2, 2)
+        add_frames(g)
+        g.set_write_callback(late)
+        raises(gifski.GifskiInvalidState, g.set_write_callback, io.BytesIO())
+        late_stats = g.finish()
+
+        for out, stats in [(early, early_stats), (late, late_stats)]:
+            assert is_gif(out.getvalue())
+            assert stats.frames_written == 3 and stats.output_bytes == len(out.getvalue())
+        assert delays(early.getvalue()) == delays(late.getvalue())
+
+        with tempfile.TemporaryDirectory() as dir:
+            path = os.path.join(dir, "late.gif")
+            g = gifski.Gifski(2, 2)
+            add_frames(g)
+            g.set_file_output(path)
+            stats = g.finish()
+            with open(path, "rb") as f:
+                gif = f.read()
+            assert is_gif(gif) and stats.output_bytes == len(gif)
+    "#);
+}
+
+#[test]
+fn alpha_threshold() {
+    assert!(alpha_threshold_arg(-1).is_err());
+    assert!(alpha_threshold_arg(256).is_err());
+    assert_eq!(alpha_threshold_arg(255).unwrap(), 255);
+    let pixels = [RGBA8::new(1, 2, 3, 0), RGBA8::new(1, 2, 3, 127), RGBA8::new(1, 2, 3, 128), RGBA8::new(1, 2, 3, 255)];
+    assert!(matches!(thresholded_alpha(&pixels, None), Cow::Borrowed(_)));
+    let alpha: Vec<_> = thresholded_alpha(&pixels, Some(128)).iter().map(|px| px.a).collect();
+    assert_eq!(alpha, [0, 0, 255, 255]);
+    assert!(thresholded_alpha(&pixels, Some(0)).iter().all(|px| px.a == 255 && px.rgb() == RGB8::new(1, 2, 3)));
+}
+
+#[test]
+fn delay_roundings() {
+    assert_eq!(delay_rounding_arg("round").unwrap(), 0);
+    assert_eq!(delay_rounding_arg("nearest_even").unwrap(), 2);
+    assert!(delay_rounding_arg("ceil").is_err());
+    for (index, name) in DELAY_ROUNDINGS.iter().enumerate() {
+        assert_eq!(delay_rounding_arg(name).unwrap() as usize, index);
+    }
+}
+
+#[test]
+fn size_samples() {
+    assert_eq!(sample_indices(3, 10), [0, 1, 2]);
+    assert_eq!(sample_indices(10, 10).len(), 10);
+    assert_eq!(sample_indices(100, 4), [0, 25, 50, 75]);
+    assert_eq!(sample_indices(11, 10), [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    assert!(sample_indices(1000, 7).windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn py_estimate_size() {
+    run_python(r#"
+        g = gifski.Gifski(8, 8, keep_frames=True)
+        raises(RuntimeError, g.estimate_size)
+        add_frames(g, 20)
+        raises(ValueError, g.estimate_size, max_samples=0)
+        estimate = g.estimate_size(max_samples=5)
+        assert g.frame_count == 20
+        size = len(g.finish_to_bytes())
+        assert size / 3 < estimate < size * 3, (estimate, size)
+
+        g = gifski.Gifski(8, 8)
+        add_frames(g, 1)
+        raises(RuntimeError, g.estimate_size)
+    "#);
+}
+
+#[test]
+fn py_pickle() {
+    run_python(r#"
+        import pickle
+        import sys
+
+        sys.modules["gifski"] = gifski
+        try:
+            g = gifski.Gifski(4, 3, quality=80, fast=2, repeat=-1, fps=12.5, motion_quality=70, lossy=20, dither=0.5,
+                              fit="pad", background=(1, 2, 3, 255), transparent_color=(0, 255, 0, 255), dedupe=True,
+                              max_frames=9, keep_frames=True, speed=2.0, auto_rebase=True, alpha_threshold=128,
+                              delay_rounding="floor")
+            g.set_palette([(0, 0, 0, 255), (255, 255, 255, 255)])
+            g.set_comment("pickled")
+            copy = pickle.loads(pickle.dumps(g))
+            assert copy.settings() == g.settings(), (copy.settings(), g.settings())
+
+            # pickling is refused once indexed frames exist
+            g = gifski.Gifski(4, 3)
+            g.add_frame_rgba_at(0, bytes(48), 0.0)
+            raises(TypeError, pickle.dumps, g)
+        finally:
+            del sys.modules["gifski"]
+    "#);
 }
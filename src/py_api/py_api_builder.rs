@@ -0,0 +1,260 @@
+use super::{alpha_threshold_arg, check_max_pixels, color_arg, comment_bytes, delay_rounding_arg, dithering_level, fast_level, fit_arg, fps_arg, init_thread_pool, lossy_arg, max_frames_arg, num_threads_arg, palette_arg, quality_arg, repeat_arg, resolve_quality, speed_arg, ExtraSettings, FastArg, Fit, FrameOptions, PyGifski, QualityArg};
+use crate::c_api::GifskiSettings;
+use pyo3::exceptions;
+use pyo3::prelude::*;
+use std::ffi::CString;
+
+/// GifskiBuilder()
+///
+/// Alternative to Gifski()'s keyword arguments. Each setter checks its
+/// argument immediately, and returns the builder, so the calls can be chained:
+///     g = GifskiBuilder().quality(Preset.HIGH).repeat(-1).dither(0.5).build(width, height)
+///
+/// Settings not set on the builder have the same defaults as in Gifski().
+/// The builder can be used to build any number of Gifski instances.
+#[pyclass]
+#[pyo3(name="GifskiBuilder")]
+pub(crate) struct PyGifskiBuilder {
+    quality: u8,
+    preset_motion_quality: Option<u8>,
+    preset_lossy_quality: Option<u8>,
+    preset_dithering_level: Option<f32>,
+    fast_level: u8,
+    repeat: i16,
+    motion_quality: Option<u8>,
+    lossy_quality: Option<u8>,
+    dithering_level: Option<f32>,
+    /// The color of `Fit::Pad` is set in build(), so that background() can be called before fit("pad")
+    options: FrameOptions,
+    background: Option<rgb::RGBA8>,
+    palette: Vec<rgb::RGBA8>,
+    transparent_color: Option<rgb::RGBA8>,
+    comment: Option<CString>,
+    max_pixels: Option<u64>,
+    delay_rounding: u8,
+    num_threads: Option<usize>,
+}
+
+#[pymethods]
+impl PyGifskiBuilder {
+    #[new]
+    fn new() -> Self {
+        Self {
+            quality: 90,
+            preset_motion_quality: None,
+            preset_lossy_quality: None,
+            preset_dithering_level: None,
+            fast_level: 1,
+            repeat: 0,
+            motion_quality: None,
+            lossy_quality: None,
+            dithering_level: None,
+            options: FrameOptions::default(),
+            background: None,
+            palette: Vec::new(),
+            transparent_color: None,
+            comment: None,
+            max_pixels: None,
+            delay_rounding: 0,
+            num_threads: None,
+        }
+    }
+
+    /// Integer from 1 to 100, or a Preset
+    #[pyo3(text_signature = "(self, quality, /)")]
+    fn quality(mut slf: PyRefMut<'_, Self>, quality: QualityArg) -> PyResult<PyRefMut<'_, Self>> {
+        let (quality, motion_quality, lossy_quality, dithering_level) = resolve_quality(quality)?;
+        slf.quality = quality;
+        slf.preset_motion_quality = motion_quality;
+        slf.preset_lossy_quality = lossy_quality;
+        slf.preset_dithering_level = dithering_level;
+        Ok(slf)
+    }
+
+    /// Speed level from 0 to 3, or True/False for 3/1
+    #[pyo3(text_signature = "(self, fast, /)")]
+    fn fast(mut slf: PyRefMut<'_, Self>, fast: FastArg) -> PyResult<PyRefMut<'_, Self>> {
+        slf.fast_level = fast_level(fast)?;
+        Ok(slf)
+    }
+
+    /// -1 for no looping, 0 for infinite looping, or n for looping n times, up to 32767
+    #[pyo3(text_signature = "(self, repeat, /)")]
+    fn repeat(mut slf: PyRefMut<'_, Self>, repeat: i64) -> PyResult<PyRefMut<'_, Self>> {
+        slf.repeat = repeat_arg(repeat)?;
+        Ok(slf)
+    }
+
+    #[pyo3(text_signature = "(self, fps, /)")]
+    fn fps(mut slf: PyRefMut<'_, Self>, fps: f64) -> PyResult<PyRefMut<'_, Self>> {
+        slf.options.fps = Some(fps_arg(fps)?);
+        Ok(slf)
+    }
+
+    /// 1-100, overrides the quality or preset
+    #[pyo3(text_signature = "(self, motion_quality, /)")]
+    fn motion_quality(mut slf: PyRefMut<'_, Self>, motion_quality: i64) -> PyResult<PyRefMut<'_, Self>> {
+        slf.motion_quality = Some(quality_arg(motion_quality, "motion_quality")?);
+        Ok(slf)
+    }
+
+    /// 1-100, overrides the quality or preset
+    #[pyo3(text_signature = "(self, lossy_quality, /)")]
+    fn lossy_quality(mut slf: PyRefMut<'_, Self>, lossy_quality: i64) -> PyResult<PyRefMut<'_, Self>> {
+        slf.lossy_quality = Some(quality_arg(lossy_quality, "lossy_quality")?);
+        Ok(slf)
+    }
+
+    /// Lossy compression strength from 0 (off) to 99, the reverse of lossy_quality
+    #[pyo3(text_signature = "(self, lossy, /)")]
+    fn lossy(mut slf: PyRefMut<'_, Self>, lossy: i64) -> PyResult<PyRefMut<'_, Self>> {
+        slf.lossy_quality = Some(lossy_arg(lossy)?);
+        Ok(slf)
+    }
+
+    /// Strength from 0.0 to 1.0, or True/False, overrides the preset
+    #[pyo3(text_signature = "(self, dither, /)")]
+    fn dither<'a>(mut slf: PyRefMut<'a, Self>, dither: &PyAny) -> PyResult<PyRefMut<'a, Self>> {
+        slf.dithering_level = Some(dithering_level(dither)?);
+        Ok(slf)
+    }
+
+    /// Same as fit("stretch") if True, or fit("error") if False
+    #[pyo3(text_signature = "(self, resize, /)")]
+    fn resize(mut slf: PyRefMut<'_, Self>, resize: bool) -> PyRefMut<'_, Self> {
+        slf.options.fit = if resize { Fit::Stretch } else { Fit::Error };
+        slf
+    }
+
+    /// "error", "stretch" or "pad", for frames of other sizes
+    #[pyo3(text_signature = "(self, fit, /)")]
+    fn fit<'a>(mut slf: PyRefMut<'a, Self>, fit: &str) -> PyResult<PyRefMut<'a, Self>> {
+        slf.options.fit = fit_arg(fit, None)?;
+        Ok(slf)
+    }
+
+    /// (r, g, b, a) color with values from 0 to 255 of the padding for fit("pad")
+    #[pyo3(text_signature = "(self, background, /)")]
+    fn background(mut slf: PyRefMut<'_, Self>, background: (i64, i64, i64, i64)) -> PyResult<PyRefMut<'_, Self>> {
+        slf.background = Some(color_arg(background)?);
+        Ok(slf)
+    }
+
+    /// List of 1 to 256 (r, g, b, a) colors to use instead of generated palettes, like Gifski.set_palette()
+    #[pyo3(text_signature = "(self, colors, /)")]
+    fn palette(mut slf: PyRefMut<'_, Self>, colors: Vec<(u8, u8, u8, u8)>) -> PyResult<PyRefMut<'_, Self>> {
+        slf.palette = palette_arg(colors)?;
+        Ok(slf)
+    }
+
+    /// (r, g, b, a) color with values from 0 to 255 to make transparent
+    #[pyo3(text_signature = "(self, transparent_color, /)")]
+    fn transparent_color(mut slf: PyRefMut<'_, Self>, transparent_color: (i64, i64, i64, i64)) -> PyResult<PyRefMut<'_, Self>> {
+        slf.transparent_color = Some(color_arg(transparent_color)?);
+        Ok(slf)
+    }
+
+    /// Latin-1 text to embed in the gif, like Gifski.set_comment(). An empty string removes it.
+    #[pyo3(text_signature = "(self, text, /)")]
+    fn comment<'a>(mut slf: PyRefMut<'a, Self>, text: &str) -> PyResult<PyRefMut<'a, Self>> {
+        let comment = comment_bytes(text).map_err(exceptions::PyValueError::new_err)?;
+        slf.comment = if text.is_empty() { None } else { Some(comment) };
+        Ok(slf)
+    }
+
+    /// Makes build() raise ValueError if width*height is larger than this
+    #[pyo3(text_signature = "(self, max_pixels, /)")]
+    fn max_pixels(mut slf: PyRefMut<'_, Self>, max_pixels: u64) -> PyRefMut<'_, Self> {
+        slf.max_pixels = Some(max_pixels);
+        slf
+    }
+
+    /// If True, frames identical to the previous frame extend it instead of being encoded
+    #[pyo3(text_signature = "(self, dedupe, /)")]
+    fn dedupe(mut slf: PyRefMut<'_, Self>, dedupe: bool) -> PyRefMut<'_, Self> {
+        slf.options.dedupe = dedupe;
+        slf
+    }
+
+    /// Makes add_frame_* methods raise ValueError after this many frames
+    #[pyo3(text_signature = "(self, max_frames, /)")]
+    fn max_frames(mut slf: PyRefMut<'_, Self>, max_frames: u32) -> PyResult<PyRefMut<'_, Self>> {
+        slf.options.max_frames = Some(max_frames_arg(max_frames)?);
+        Ok(slf)
+    }
+
+    /// If True, copies of the frames are kept for finish_thumbnail(), preview_palette(), finish_to_size() and estimate_size()
+    #[pyo3(text_signature = "(self, keep_frames, /)")]
+    fn keep_frames(mut slf: PyRefMut<'_, Self>, keep_frames: bool) -> PyRefMut<'_, Self> {
+        slf.options.keep_frames = keep_frames;
+        slf
+    }
+
+    /// Playback speed, e.g. 2.0 for twice as fast
+    #[pyo3(text_signature = "(self, speed, /)")]
+    fn speed(mut slf: PyRefMut<'_, Self>, speed: f64) -> PyResult<PyRefMut<'_, Self>> {
+        slf.options.speed = speed_arg(speed)?;
+        Ok(slf)
+    }
+
+    /// If True, timestamps are relative to the first frame's, which doesn't have to be 0
+    #[pyo3(text_signature = "(self, auto_rebase, /)")]
+    fn auto_rebase(mut slf: PyRefMut<'_, Self>, auto_rebase: bool) -> PyRefMut<'_, Self> {
+        slf.options.auto_rebase = auto_rebase;
+        slf
+    }
+
+    /// 0-255, alpha below it becomes fully transparent, and the rest fully opaque
+    #[pyo3(text_signature = "(self, alpha_threshold, /)")]
+    fn alpha_threshold(mut slf: PyRefMut<'_, Self>, alpha_threshold: i64) -> PyResult<PyRefMut<'_, Self>> {
+        slf.options.alpha_threshold = Some(alpha_threshold_arg(alpha_threshold)?);
+        Ok(slf)
+    }
+
+    /// "round", "floor" or "nearest_even", for converting timestamps to hundredths of a second
+    #[pyo3(text_signature = "(self, delay_rounding, /)")]
+    fn delay_rounding<'a>(mut slf: PyRefMut<'a, Self>, delay_rounding: &str) -> PyResult<PyRefMut<'a, Self>> {
+        slf.delay_rounding = delay_rounding_arg(delay_rounding)?;
+        Ok(slf)
+    }
+
+    /// Calls set_num_threads() in build(), so it's for all Gifski objects in the process
+    #[pyo3(text_signature = "(self, num_threads, /)")]
+    fn num_threads(mut slf: PyRefMut<'_, Self>, num_threads: usize) -> PyResult<PyRefMut<'_, Self>> {
+        slf.num_threads = Some(num_threads_arg(num_threads)?);
+        Ok(slf)
+    }
+
+    /// Creates a Gifski with the settings of this builder
+    #[pyo3(text_signature = "(self, width, height, /)")]
+    unsafe fn build(&self, width: u32, height: u32) -> PyResult<PyGifski> {
+        check_max_pixels(width, height, self.max_pixels)?;
+        let settings = GifskiSettings {
+            width, height,
+            quality: self.quality,
+            fast: self.fast_level == 3,
+            repeat: self.repeat,
+        };
+        let options = FrameOptions {
+            fit: match self.options.fit {
+                Fit::Pad(_) => fit_arg("pad", self.background)?,
+                fit => fit,
+            },
+            ..self.options
+        };
+        let extra_settings = ExtraSettings {
+            fast_level: self.fast_level,
+            motion_quality: self.motion_quality.or(self.preset_motion_quality).unwrap_or(self.quality),
+            lossy_quality: self.lossy_quality.or(self.preset_lossy_quality).unwrap_or(self.quality),
+            dithering_level: self.dithering_level.or(self.preset_dithering_level),
+            fixed_palette: self.palette.clone(),
+            transparent_color: self.transparent_color,
+            comment: self.comment.clone(),
+            delay_rounding: self.delay_rounding,
+        };
+        if let Some(num_threads) = self.num_threads {
+            init_thread_pool(num_threads)?;
+        }
+        PyGifski::with_settings(settings, extra_settings, options)
+    }
+}
@@ -0,0 +1,109 @@
+use crate::c_api::GifskiError as ErrorCode;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+create_exception!(gifski, GifskiError, PyException, "Base class for errors reported by gifski. Has `code` (int) and `name` (str) of the error, and gifski's `message` (str or None) explaining it.");
+create_exception!(gifski, GifskiNullArg, GifskiError, "NULL_ARG: a required argument was missing.");
+create_exception!(gifski, GifskiInvalidState, GifskiError, "INVALID_STATE: the method can't be called at this point, e.g. the output has already been set.");
+create_exception!(gifski, GifskiQuant, GifskiError, "QUANT: color quantization has failed.");
+create_exception!(gifski, GifskiGif, GifskiError, "GIF: the gif couldn't be encoded.");
+create_exception!(gifski, GifskiThreadLost, GifskiError, "THREAD_LOST: an encoding thread has stopped unexpectedly.");
+create_exception!(gifski, GifskiNotFound, GifskiError, "NOT_FOUND: a file or directory doesn't exist.");
+create_exception!(gifski, GifskiPermissionDenied, GifskiError, "PERMISSION_DENIED: a file isn't readable or writable.");
+create_exception!(gifski, GifskiAlreadyExists, GifskiError, "ALREADY_EXISTS: the output file already exists.");
+create_exception!(gifski, GifskiInvalidInput, GifskiError, "INVALID_INPUT: invalid frame data or arguments.");
+create_exception!(gifski, GifskiTimedOut, GifskiError, "TIMED_OUT: an I/O operation has timed out.");
+create_exception!(gifski, GifskiWriteZero, GifskiError, "WRITE_ZERO: the output didn't accept all of the data.");
+create_exception!(gifski, GifskiInterrupted, GifskiError, "INTERRUPTED: an I/O operation was interrupted.");
+create_exception!(gifski, GifskiUnexpectedEof, GifskiError, "UNEXPECTED_EOF: an input file has ended too early.");
+create_exception!(gifski, GifskiAborted, GifskiError, "ABORTED: the encoding has been aborted.");
+create_exception!(gifski, GifskiOther, GifskiError, "OTHER: any other error.");
+
+/// Exception subclass matching the C API's error code
+pub(crate) fn error_from_code(code: ErrorCode) -> PyErr {
+    error_with_message(code, None)
+}
+
+/// Like `error_from_code`, with a message from the error message callback
+pub(crate) fn error_with_message(code: ErrorCode, gifski_message: Option<&str>) -> PyErr {
+    let message = error_text(code, gifski_message);
+    let err = match code {
+        ErrorCode::NULL_ARG => GifskiNullArg::new_err(message),
+        ErrorCode::INVALID_STATE => GifskiInvalidState::new_err(message),
+        ErrorCode::QUANT => GifskiQuant::new_err(message),
+        ErrorCode::GIF => GifskiGif::new_err(message),
+        ErrorCode::THREAD_LOST => GifskiThreadLost::new_err(message),
+        ErrorCode::NOT_FOUND => GifskiNotFound::new_err(message),
+        ErrorCode::PERMISSION_DENIED => GifskiPermissionDenied::new_err(message),
+        ErrorCode::ALREADY_EXISTS => GifskiAlreadyExists::new_err(message),
+        ErrorCode::INVALID_INPUT => GifskiInvalidInput::new_err(message),
+        ErrorCode::TIMED_OUT => GifskiTimedOut::new_err(message),
+        ErrorCode::WRITE_ZERO => GifskiWriteZero::new_err(message),
+        ErrorCode::INTERRUPTED => GifskiInterrupted::new_err(message),
+        ErrorCode::UNEXPECTED_EOF => GifskiUnexpectedEof::new_err(message),
+        ErrorCode::ABORTED => GifskiAborted::new_err(message),
+        // OK isn't an error, but it's not worth panicking over
+        ErrorCode::OK | ErrorCode::OTHER => GifskiOther::new_err(message),
+    };
+    // the callers hold the GIL already
+    Python::with_gil(|py| {
+        let value = err.value(py);
+        // exception instances have a __dict__, so this can't fail
+        let _ = value.setattr("code", code as u8);
+        let _ = value.setattr("name", code.to_string());
+        let _ = value.setattr("message", gifski_message);
+    });
+    err
+}
+
+fn error_text(code: ErrorCode, gifski_message: Option<&str>) -> String {
+    match gifski_message {
+        Some(gifski_message) => format!("{} (gifski error code {}): {}", code, code as u8, gifski_message),
+        None => format!("{} (gifski error code {})", code, code as u8),
+    }
+}
+
+/// Name of a `GifskiError` value, or "UNKNOWN"
+pub(crate) fn error_code_name(code: i64) -> String {
+    // the conversion from c_int maps unknown codes to OTHER
+    if !(0..=ErrorCode::OTHER as i64).contains(&code) {
+        return "UNKNOWN".into();
+    }
+    ErrorCode::from(code as std::os::raw::c_int).to_string()
+}
+
+pub(crate) fn add_exceptions(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("GifskiError", py.get_type::<GifskiError>())?;
+    m.add("GifskiNullArg", py.get_type::<GifskiNullArg>())?;
+    m.add("GifskiInvalidState", py.get_type::<GifskiInvalidState>())?;
+    m.add("GifskiQuant", py.get_type::<GifskiQuant>())?;
+    m.add("GifskiGif", py.get_type::<GifskiGif>())?;
+    m.add("GifskiThreadLost", py.get_type::<GifskiThreadLost>())?;
+    m.add("GifskiNotFound", py.get_type::<GifskiNotFound>())?;
+    m.add("GifskiPermissionDenied", py.get_type::<GifskiPermissionDenied>())?;
+    m.add("GifskiAlreadyExists", py.get_type::<GifskiAlreadyExists>())?;
+    m.add("GifskiInvalidInput", py.get_type::<GifskiInvalidInput>())?;
+    m.add("GifskiTimedOut", py.get_type::<GifskiTimedOut>())?;
+    m.add("GifskiWriteZero", py.get_type::<GifskiWriteZero>())?;
+    m.add("GifskiInterrupted", py.get_type::<GifskiInterrupted>())?;
+    m.add("GifskiUnexpectedEof", py.get_type::<GifskiUnexpectedEof>())?;
+    m.add("GifskiAborted", py.get_type::<GifskiAborted>())?;
+    m.add("GifskiOther", py.get_type::<GifskiOther>())?;
+    Ok(())
+}
+
+#[test]
+fn error_texts() {
+    assert_eq!("NOT_FOUND (gifski error code 6)", error_text(ErrorCode::NOT_FOUND, None));
+    assert_eq!("INVALID_STATE (gifski error code 2): already started", error_text(ErrorCode::INVALID_STATE, Some("already started")));
+}
+
+#[test]
+fn error_names() {
+    assert_eq!("OK", error_code_name(0));
+    assert_eq!("INVALID_STATE", error_code_name(ErrorCode::INVALID_STATE as i64));
+    assert_eq!("OTHER", error_code_name(ErrorCode::OTHER as i64));
+    assert_eq!("UNKNOWN", error_code_name(ErrorCode::OTHER as i64 + 1));
+    assert_eq!("UNKNOWN", error_code_name(-1));
+}